@@ -0,0 +1,272 @@
+use crate::memory::IoReg;
+
+/// Value for NR52 turning the APU on, nothing else in the APU can be written while it's off
+pub const APU_ENABLE: u8 = 0x80;
+
+/// Marks the end of a [SoundEffect] in its encoded form
+pub const SFX_END: u8 = 0xff;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Pulse1,
+    Pulse2,
+    Wave,
+    Noise,
+}
+
+impl Channel {
+    /// Bit for the channel in the low nibble of NR51 and NR52, the high nibble of NR51 is the same shifted left by 4
+    pub fn bit(&self) -> u8 {
+        1 << *self as u8
+    }
+}
+
+/// Share of each pulse period spent high
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Duty {
+    Eighth,
+    Quarter,
+    #[default]
+    Half,
+    ThreeQuarters,
+}
+
+/// Volume envelope of the pulse and noise channels, NRx2
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Envelope {
+    /// Initial volume, `0..=15`. An envelope starting at 0 and not increasing turns the channel off
+    pub volume: u8,
+    pub increase: bool,
+    /// Volume steps every `pace` 64ths of a second, `0..=7`, 0 keeps the volume fixed
+    pub pace: u8,
+}
+
+impl Envelope {
+    pub fn fixed(volume: u8) -> Self {
+        Self { volume, increase: false, pace: 0 }
+    }
+
+    pub fn fade_out(volume: u8, pace: u8) -> Self {
+        Self { volume, increase: false, pace }
+    }
+
+    fn is_valid(&self) -> bool {
+        self.volume < 16 && self.pace < 8
+    }
+}
+
+impl From<Envelope> for u8 {
+    fn from(value: Envelope) -> Self {
+        value.volume << 4 | (value.increase as u8) << 3 | value.pace
+    }
+}
+
+/// Period sweep of pulse channel 1, NR10
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Sweep {
+    /// Sweeps every `pace` 128ths of a second, `0..=7`, 0 disables the sweep
+    pub pace: u8,
+    /// Lowers the pitch instead of raising it
+    pub down: bool,
+    /// Each iteration changes the period by `period >> step`, `0..=7`
+    pub step: u8,
+}
+
+impl Sweep {
+    fn is_valid(&self) -> bool {
+        self.pace < 8 && self.step < 8
+    }
+}
+
+impl From<Sweep> for u8 {
+    fn from(value: Sweep) -> Self {
+        value.pace << 4 | (value.down as u8) << 3 | value.step
+    }
+}
+
+/// Output level of the wave channel, NR32
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum WaveVolume {
+    Mute,
+    #[default]
+    Full,
+    Half,
+    Quarter,
+}
+
+impl From<WaveVolume> for u8 {
+    fn from(value: WaveVolume) -> Self {
+        (value as u8) << 5
+    }
+}
+
+/// A note on one of the pulse channels
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Pulse {
+    pub duty: Duty,
+    /// Cuts the note off after `length` 256ths of a second, `1..=64`. Plays until changed if `None`
+    pub length: Option<u8>,
+    pub envelope: Envelope,
+    /// 11-bit period, the tone is `131072 / (2048 - period)` Hz
+    pub period: u16,
+}
+
+impl Pulse {
+    fn is_valid(&self) -> bool {
+        self.envelope.is_valid() && self.period < 0x800 && self.length.is_none_or(|length| (1..=64).contains(&length))
+    }
+}
+
+/// A note on the wave channel, playing whatever is in wave RAM
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Wave {
+    pub volume: WaveVolume,
+    /// Cuts the note off after `length` 256ths of a second, `1..=256`. Plays until changed if `None`
+    pub length: Option<u16>,
+    /// 11-bit period, the tone is `65536 / (2048 - period)` Hz
+    pub period: u16,
+}
+
+impl Wave {
+    fn is_valid(&self) -> bool {
+        self.period < 0x800 && self.length.is_none_or(|length| (1..=256).contains(&length))
+    }
+}
+
+/// A burst on the noise channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Noise {
+    /// Cuts the noise off after `length` 256ths of a second, `1..=64`. Plays until changed if `None`
+    pub length: Option<u8>,
+    pub envelope: Envelope,
+    /// `0..=15`, higher is lower pitched
+    pub clock_shift: u8,
+    /// 7-bit LFSR, giving a more metallic, periodic sound
+    pub short: bool,
+    /// `0..=7`, higher is lower pitched
+    pub divider: u8,
+}
+
+impl Noise {
+    fn is_valid(&self) -> bool {
+        self.envelope.is_valid() && self.clock_shift < 16 && self.divider < 8 && self.length.is_none_or(|length| (1..=64).contains(&length))
+    }
+}
+
+/// Something a [SoundEffect] does to a channel at the start of a frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SfxEvent {
+    /// Triggers pulse channel 1, which is the only one with a [Sweep]
+    Pulse1(Pulse, Sweep),
+    /// Triggers pulse channel 2
+    Pulse2(Pulse),
+    /// Triggers the wave channel
+    Wave(Wave),
+    /// Triggers the noise channel
+    Noise(Noise),
+    /// Silences a channel by turning its DAC off
+    Stop(Channel),
+}
+
+impl SfxEvent {
+    /// Whether every field is in range for its register
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Self::Pulse1(pulse, sweep) => pulse.is_valid() && sweep.is_valid(),
+            Self::Pulse2(pulse) => pulse.is_valid(),
+            Self::Wave(wave) => wave.is_valid(),
+            Self::Noise(noise) => noise.is_valid(),
+            Self::Stop(_) => true,
+        }
+    }
+
+    /// Register writes performing the event, in order. The last write of a trigger sets bit 7 of NRx4
+    pub fn writes(&self) -> Vec<(IoReg, u8)> {
+        let control = |length_enable: bool, period: u16| 0x80 | (length_enable as u8) << 6 | (period >> 8) as u8 & 0x07;
+
+        match *self {
+            Self::Pulse1(pulse, sweep) => vec![
+                (IoReg::Nr10, sweep.into()),
+                (IoReg::Nr11, (pulse.duty as u8) << 6 | (64 - pulse.length.unwrap_or(64)) & 0x3f),
+                (IoReg::Nr12, pulse.envelope.into()),
+                (IoReg::Nr13, pulse.period as u8),
+                (IoReg::Nr14, control(pulse.length.is_some(), pulse.period)),
+            ],
+            Self::Pulse2(pulse) => vec![
+                (IoReg::Nr21, (pulse.duty as u8) << 6 | (64 - pulse.length.unwrap_or(64)) & 0x3f),
+                (IoReg::Nr22, pulse.envelope.into()),
+                (IoReg::Nr23, pulse.period as u8),
+                (IoReg::Nr24, control(pulse.length.is_some(), pulse.period)),
+            ],
+            Self::Wave(wave) => vec![
+                (IoReg::Nr30, 0x80),
+                (IoReg::Nr31, (256 - wave.length.unwrap_or(256)) as u8),
+                (IoReg::Nr32, wave.volume.into()),
+                (IoReg::Nr33, wave.period as u8),
+                (IoReg::Nr34, control(wave.length.is_some(), wave.period)),
+            ],
+            Self::Noise(noise) => vec![
+                (IoReg::Nr41, (64 - noise.length.unwrap_or(64)) & 0x3f),
+                (IoReg::Nr42, noise.envelope.into()),
+                (IoReg::Nr43, noise.clock_shift << 4 | (noise.short as u8) << 3 | noise.divider),
+                (IoReg::Nr44, control(noise.length.is_some(), 0)),
+            ],
+            Self::Stop(Channel::Pulse1) => vec![(IoReg::Nr12, 0)],
+            Self::Stop(Channel::Pulse2) => vec![(IoReg::Nr22, 0)],
+            Self::Stop(Channel::Wave) => vec![(IoReg::Nr30, 0)],
+            Self::Stop(Channel::Noise) => vec![(IoReg::Nr42, 0)],
+        }
+    }
+}
+
+/// A sequence of frames, each a list of [SfxEvent]s applied at the start of that frame
+/// 
+/// Played by [MacroAssembler::play_sfx](crate::codegen::MacroAssembler::play_sfx) and advanced one frame per
+/// [MacroAssembler::step_sfx](crate::codegen::MacroAssembler::step_sfx)
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct SoundEffect {
+    pub frames: Vec<Vec<SfxEvent>>,
+}
+
+impl SoundEffect {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a frame applying `events`
+    pub fn frame(&mut self, events: impl IntoIterator<Item = SfxEvent>) -> &mut Self {
+        self.frames.push(events.into_iter().collect());
+        self
+    }
+
+    /// Adds `frames` frames doing nothing, letting the channels play on
+    pub fn wait(&mut self, frames: usize) -> &mut Self {
+        self.frames.extend(std::iter::repeat_n(Vec::new(), frames));
+        self
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.frames.iter().all(|frame| {
+            frame.iter().all(SfxEvent::is_valid)
+                && frame.iter().map(|event| event.writes().len()).sum::<usize>() < SFX_END as usize
+        })
+    }
+
+    /// Encodes the effect for the stepper: every frame is a write count followed by that many
+    /// `(low byte of register, value)` pairs, and [SFX_END] follows the last frame
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        for frame in self.frames.iter() {
+            let writes: Vec<(IoReg, u8)> = frame.iter().flat_map(SfxEvent::writes).collect();
+            out.push(writes.len() as u8);
+            for (reg, value) in writes {
+                out.push(reg.into());
+                out.push(value);
+            }
+        }
+
+        out.push(SFX_END);
+        out
+    }
+}
@@ -0,0 +1,199 @@
+pub mod header;
+
+use std::io::{self, Write};
+
+use crate::codegen::{listing::Listing, symbols::SymbolTable};
+use crate::memory::Addr;
+
+pub use header::{
+    CgbFlag,
+    Header,
+    HeaderBuilder,
+    HeaderError,
+    Licensee,
+};
+
+/// Size of a single ROM bank in bytes
+pub const ROM_BANK_SIZE: usize = 0x4000;
+/// Start of the fixed ROM bank (ROM0)
+pub const ROM0_BASE: Addr = 0x0000;
+/// Start of the switchable ROM bank (ROMX)
+pub const ROMX_BASE: Addr = 0x4000;
+/// Writing a bank number here selects the ROMX bank on MBC1, MBC3 and MBC5
+pub const ROM_BANK_SELECT: Addr = 0x2000;
+
+/// Main code starts here, right after the header
+pub const CODE_BASE: Addr = 0x0150;
+/// ROM0 constants start here, so main code has to fit between [CODE_BASE] and this
+pub const ROM0_CONST_BASE: Addr = 0x2000;
+
+/// HRAM byte shadowing the currently selected ROMX bank, since the MBC registers are write-only
+pub const ROM_BANK_SHADOW: Addr = 0xff80;
+
+/// Far call trampoline in ROM0, right after the interrupt vectors
+///
+/// Expects the target bank in `a` and the target address in `hl`
+pub const FAR_CALL: Addr = 0x0068;
+/// `jp hl` stub used by [FAR_CALL] to call into the target
+pub const JP_HL: Addr = 0x0080;
+/// Far jump trampoline in ROM0
+///
+/// Expects the target bank in `a` and the target address in `hl`
+pub const FAR_JP: Addr = 0x0081;
+
+/// Start of the cartridge RAM window
+pub const SRAM_BASE: Addr = 0xa000;
+/// Size of a single cartridge RAM bank in bytes
+pub const SRAM_BANK_SIZE: usize = 0x2000;
+/// Writing [RAM_ENABLE_VALUE] here enables cartridge RAM, anything else disables it
+pub const RAM_ENABLE: Addr = 0x0000;
+pub const RAM_ENABLE_VALUE: u8 = 0x0a;
+/// Writing a bank number here selects the cartridge RAM bank
+pub const RAM_BANK_SELECT: Addr = 0x4000;
+/// MBC1 only: writing 1 here lets [RAM_BANK_SELECT] switch RAM banks
+pub const MBC1_MODE_SELECT: Addr = 0x6000;
+
+/// Memory bank controller present on the cartridge
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Mbc {
+    /// 32 KiB, no bank switching
+    #[default]
+    RomOnly,
+    Mbc1,
+    Mbc3,
+    Mbc5,
+}
+
+impl Mbc {
+    /// Highest number of ROM banks gleeby can address on this MBC
+    ///
+    /// Only the lower bank register at [ROM_BANK_SELECT] is used, so this caps MBC1 at 512 KiB and MBC5 at 4 MiB
+    pub fn max_banks(&self) -> u16 {
+        match self {
+            Self::RomOnly => 2,
+            Self::Mbc1 => 32,
+            Self::Mbc3 => 128,
+            Self::Mbc5 => 256,
+        }
+    }
+
+    /// Highest number of 8 KiB cartridge RAM banks on this MBC
+    pub fn max_ram_banks(&self) -> u16 {
+        match self {
+            Self::RomOnly => 0,
+            Self::Mbc1 => 4,
+            Self::Mbc3 => 4,
+            Self::Mbc5 => 16,
+        }
+    }
+
+    /// Whether writes to [ROM_BANK_SELECT] do anything
+    pub fn is_banked(&self) -> bool {
+        *self != Self::RomOnly
+    }
+
+    /// Cartridge type byte for the header at 0x147
+    /// 
+    /// Cartridge RAM is always assumed to be battery backed
+    pub fn cartridge_type(&self, has_ram: bool) -> u8 {
+        match (self, has_ram) {
+            (Self::RomOnly, _) => 0x00,
+            (Self::Mbc1, false) => 0x01,
+            (Self::Mbc1, true) => 0x03,
+            (Self::Mbc3, false) => 0x11,
+            (Self::Mbc3, true) => 0x13,
+            (Self::Mbc5, false) => 0x19,
+            (Self::Mbc5, true) => 0x1b,
+        }
+    }
+}
+
+/// ROM size byte for the header at 0x148, rounding `banks` up to the next power of two
+pub fn rom_size_code(banks: u16) -> u8 {
+    let banks = banks.max(2).next_power_of_two();
+    (banks.trailing_zeros() - 1) as u8
+}
+
+/// RAM size byte for the header at 0x149
+pub fn ram_size_code(banks: u16) -> u8 {
+    match banks {
+        0 => 0x00,
+        1 => 0x02,
+        2..=4 => 0x03,
+        5..=8 => 0x05,
+        _ => 0x04,
+    }
+}
+
+/// An address in a specific ROM or cartridge RAM bank
+///
+/// For ROM, bank 0 is ROM0 and anything else lives in the ROMX window
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BankedAddr {
+    pub bank: u16,
+    pub addr: Addr,
+}
+
+impl BankedAddr {
+    /// Offset of this address in the ROM image
+    pub fn rom_offset(&self) -> usize {
+        if self.bank == 0 {
+            self.addr as usize
+        } else {
+            self.bank as usize * ROM_BANK_SIZE + (self.addr - ROMX_BASE) as usize
+        }
+    }
+}
+
+impl From<Addr> for BankedAddr {
+    fn from(addr: Addr) -> Self {
+        Self { bank: 0, addr }
+    }
+}
+
+/// A finished ROM, padded out to the size in its header
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RomImage {
+    pub bytes: Vec<u8>,
+    pub symbols: SymbolTable,
+    pub listing: Listing,
+}
+
+impl RomImage {
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn write_to<W>(&self, out: &mut W) -> io::Result<()>
+            where W: Write {
+        out.write_all(&self.bytes)
+    }
+
+    /// Writes the `.sym` file to load alongside the ROM in an emulator
+    pub fn write_sym<W>(&self, out: &mut W) -> io::Result<()>
+            where W: Write {
+        self.symbols.write_to(out)
+    }
+
+    /// Writes the assembly listing, with every instruction's address, bytes, and the blocks and macros it came from
+    pub fn write_listing<W>(&self, out: &mut W) -> io::Result<()>
+            where W: Write {
+        self.listing.write_to(out)
+    }
+}
+
+impl AsRef<[u8]> for RomImage {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl From<RomImage> for Vec<u8> {
+    fn from(value: RomImage) -> Self {
+        value.bytes
+    }
+}
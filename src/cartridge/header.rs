@@ -0,0 +1,260 @@
+use std::fmt::Display;
+
+use super::{ram_size_code, rom_size_code, Mbc};
+
+/// Checked by the boot ROM, the cartridge won't boot on real hardware without it
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xce, 0xed, 0x66, 0x66, 0xcc, 0x0d, 0x00, 0x0b, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0c, 0x00, 0x0d,
+    0x00, 0x08, 0x11, 0x1f, 0x88, 0x89, 0x00, 0x0e, 0xdc, 0xcc, 0x6e, 0xe6, 0xdd, 0xdd, 0xd9, 0x99,
+    0xbb, 0xbb, 0x67, 0x63, 0x6e, 0x0e, 0xec, 0xcc, 0xdd, 0xdc, 0x99, 0x9f, 0xbb, 0xb9, 0x33, 0x3e,
+];
+
+pub const HEADER_START: usize = 0x0100;
+pub const HEADER_END: usize = 0x0150;
+
+const LOGO: usize = 0x0104;
+const TITLE: usize = 0x0134;
+const MANUFACTURER: usize = 0x013f;
+const CGB_FLAG: usize = 0x0143;
+const NEW_LICENSEE: usize = 0x0144;
+const SGB_FLAG: usize = 0x0146;
+const CARTRIDGE_TYPE: usize = 0x0147;
+const ROM_SIZE: usize = 0x0148;
+const RAM_SIZE: usize = 0x0149;
+const DESTINATION: usize = 0x014a;
+const OLD_LICENSEE: usize = 0x014b;
+const VERSION: usize = 0x014c;
+const HEADER_CHECKSUM: usize = 0x014d;
+const GLOBAL_CHECKSUM: usize = 0x014e;
+
+/// Old licensee value meaning "look at the new licensee code instead"
+const USE_NEW_LICENSEE: u8 = 0x33;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum CgbFlag {
+    /// Uses CGB features, but still runs on DMG
+    #[default]
+    Enhanced,
+    /// Refuses to run on DMG
+    Only,
+    /// No CGB features, CGB runs it in DMG compatibility mode
+    Dmg,
+}
+
+impl From<CgbFlag> for u8 {
+    fn from(value: CgbFlag) -> Self {
+        match value {
+            CgbFlag::Enhanced => 0x80,
+            CgbFlag::Only => 0xc0,
+            CgbFlag::Dmg => 0x00,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Licensee {
+    /// Single byte code at 0x14b
+    Old(u8),
+    /// Two ASCII characters at 0x144, required for SGB support
+    New([u8; 2]),
+}
+
+impl Default for Licensee {
+    fn default() -> Self {
+        Self::Old(0x00)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderError {
+    /// The title was longer than the space left by the manufacturer code
+    TitleTooLong { max: usize, len: usize },
+    /// Titles and manufacturer codes have to be ASCII
+    NonAscii,
+    /// SGB features are only enabled with a new licensee code
+    SgbNeedsNewLicensee,
+    /// ROM size codes go from 0x00 (32 KiB) up to [MAX_ROM_SIZE_CODE] (8 MiB)
+    InvalidRomSize(u8),
+}
+
+impl Display for HeaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TitleTooLong { max, len } => write!(f, "TitleTooLong: Expected at most {max} bytes, got {len}"),
+            Self::InvalidRomSize(code) => write!(f, "InvalidRomSize: Expected a code up to {MAX_ROM_SIZE_CODE:#04x}, got {code:#04x}"),
+            _ => write!(f, "{self:?}"),
+        }
+    }
+}
+
+/// Biggest ROM size code, 8 MiB
+pub const MAX_ROM_SIZE_CODE: u8 = 0x08;
+
+/// Header fields, with anything left unset derived from the ROM layout at build time
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct HeaderBuilder {
+    title: String,
+    manufacturer: Option<[u8; 4]>,
+    cgb_flag: CgbFlag,
+    licensee: Licensee,
+    sgb: bool,
+    cartridge_type: Option<u8>,
+    rom_size: Option<u8>,
+    ram_size: Option<u8>,
+    version: u8,
+}
+
+impl HeaderBuilder {
+    /// Up to 15 ASCII characters, or 11 with a manufacturer code
+    pub fn title(&mut self, title: &str) -> &mut Self {
+        self.title = title.to_owned();
+        self
+    }
+
+    pub fn manufacturer(&mut self, code: [u8; 4]) -> &mut Self {
+        self.manufacturer = Some(code);
+        self
+    }
+
+    pub fn cgb_flag(&mut self, flag: CgbFlag) -> &mut Self {
+        self.cgb_flag = flag;
+        self
+    }
+
+    pub fn licensee(&mut self, licensee: Licensee) -> &mut Self {
+        self.licensee = licensee;
+        self
+    }
+
+    pub fn sgb(&mut self, sgb: bool) -> &mut Self {
+        self.sgb = sgb;
+        self
+    }
+
+    /// Overrides the cartridge type derived from the [Mbc]
+    pub fn cartridge_type(&mut self, cartridge_type: u8) -> &mut Self {
+        self.cartridge_type = Some(cartridge_type);
+        self
+    }
+
+    /// Overrides the ROM size derived from the number of banks used
+    pub fn rom_size(&mut self, rom_size: u8) -> &mut Self {
+        self.rom_size = Some(rom_size);
+        self
+    }
+
+    /// Overrides the RAM size derived from the cartridge RAM allocated
+    pub fn ram_size(&mut self, ram_size: u8) -> &mut Self {
+        self.ram_size = Some(ram_size);
+        self
+    }
+
+    pub fn version(&mut self, version: u8) -> &mut Self {
+        self.version = version;
+        self
+    }
+
+    /// Resolves the header for a cartridge using `mbc` with `rom_banks` ROM banks and `ram_banks` RAM banks
+    pub fn build(&self, mbc: Mbc, rom_banks: u16, ram_banks: u16) -> Result<Header, HeaderError> {
+        let max = if self.manufacturer.is_some() { 11 } else { 15 };
+
+        if !self.title.is_ascii() || self.manufacturer.is_some_and(|code| !code.is_ascii()) {
+            Err(HeaderError::NonAscii)?
+        }
+
+        if self.title.len() > max {
+            Err(HeaderError::TitleTooLong { max, len: self.title.len() })?
+        }
+
+        if self.sgb && !matches!(self.licensee, Licensee::New(_)) {
+            Err(HeaderError::SgbNeedsNewLicensee)?
+        }
+
+        let rom_size = self.rom_size.unwrap_or(rom_size_code(rom_banks));
+        if rom_size > MAX_ROM_SIZE_CODE {
+            Err(HeaderError::InvalidRomSize(rom_size))?
+        }
+
+        let mut title = [0; 15];
+        title[..self.title.len()].copy_from_slice(self.title.as_bytes());
+
+        Ok(Header {
+            title,
+            manufacturer: self.manufacturer,
+            cgb_flag: self.cgb_flag,
+            licensee: self.licensee,
+            sgb: self.sgb,
+            cartridge_type: self.cartridge_type.unwrap_or(mbc.cartridge_type(ram_banks > 0)),
+            rom_size,
+            ram_size: self.ram_size.unwrap_or(ram_size_code(ram_banks)),
+            version: self.version,
+        })
+    }
+}
+
+/// Fully resolved cartridge header, from the logo at 0x104 up to the checksums at 0x14d
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub title: [u8; 15],
+    pub manufacturer: Option<[u8; 4]>,
+    pub cgb_flag: CgbFlag,
+    pub licensee: Licensee,
+    pub sgb: bool,
+    pub cartridge_type: u8,
+    pub rom_size: u8,
+    pub ram_size: u8,
+    pub version: u8,
+}
+
+impl Header {
+    /// Writes every header field except the entry point into `rom`, then fixes up both checksums
+    /// 
+    /// The global checksum covers the whole image, so this has to happen last
+    pub fn write_to(&self, rom: &mut [u8]) {
+        rom[LOGO..LOGO + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+        rom[TITLE..TITLE + self.title.len()].copy_from_slice(&self.title);
+
+        if let Some(code) = self.manufacturer {
+            rom[MANUFACTURER..MANUFACTURER + code.len()].copy_from_slice(&code);
+        }
+
+        rom[CGB_FLAG] = self.cgb_flag.into();
+
+        match self.licensee {
+            Licensee::Old(code) => {
+                rom[NEW_LICENSEE..NEW_LICENSEE + 2].copy_from_slice(&[0, 0]);
+                rom[OLD_LICENSEE] = code;
+            }
+            Licensee::New(code) => {
+                rom[NEW_LICENSEE..NEW_LICENSEE + 2].copy_from_slice(&code);
+                rom[OLD_LICENSEE] = USE_NEW_LICENSEE;
+            }
+        }
+
+        rom[SGB_FLAG] = if self.sgb { 0x03 } else { 0x00 };
+        rom[CARTRIDGE_TYPE] = self.cartridge_type;
+        rom[ROM_SIZE] = self.rom_size;
+        rom[RAM_SIZE] = self.ram_size;
+        // overseas
+        rom[DESTINATION] = 0x01;
+        rom[VERSION] = self.version;
+
+        rom[HEADER_CHECKSUM] = header_checksum(rom);
+
+        let global = global_checksum(rom);
+        rom[GLOBAL_CHECKSUM..GLOBAL_CHECKSUM + 2].copy_from_slice(&global.to_be_bytes());
+    }
+}
+
+/// Checksum over 0x134..=0x14c, verified by the boot ROM
+pub fn header_checksum(rom: &[u8]) -> u8 {
+    rom[TITLE..HEADER_CHECKSUM].iter().fold(0u8, |acc, byte| acc.wrapping_sub(*byte).wrapping_sub(1))
+}
+
+/// Sum of every byte in the ROM except the global checksum itself
+pub fn global_checksum(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|(idx, _)| *idx != GLOBAL_CHECKSUM && *idx != GLOBAL_CHECKSUM + 1)
+        .fold(0u16, |acc, (_, byte)| acc.wrapping_add(*byte as u16))
+}
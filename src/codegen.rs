@@ -2,7 +2,11 @@ pub mod allocator;
 pub mod assembler;
 pub mod block;
 pub mod cgb;
+pub mod dmg;
+pub mod listing;
 pub mod meta_instr;
+pub mod state;
+pub mod symbols;
 pub mod variables;
 
 use std::fmt::Display;
@@ -31,6 +35,9 @@ pub use variables::{
 
 pub(crate) use variables::IdInner;
 
+use crate::cartridge::HeaderError;
+use state::{LcdState, Model};
+use crate::memory::IoReg;
 use crate::cpu::{GpRegister, IndirectPair, RegConversionError, RegisterPair, SplitError, StackPair};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -41,6 +48,30 @@ pub enum AssemblerError {
     EmitterError(EmitterError),
     RegSplitError(SplitError),
     SizeError(usize, usize),
+    /// Disabling the LCD outside VBlank can damage DMG hardware
+    UnsafeLcdDisable(LcdState),
+    /// Sprites go through the shadow OAM, which [MacroAssembler::init_oam_dma] has to set up first
+    OamDmaUninitialized,
+    /// [MacroAssembler::init_joypad] has to allocate the joypad variables first
+    JoypadUninitialized,
+    /// [MacroAssembler::init_sound] has to turn the APU on and allocate the sound effect pointer first
+    SoundUninitialized,
+    /// [MacroAssembler::init_fade] has to allocate the fade pointer first
+    FadeUninitialized,
+    /// Code in ROM bank `bank` tried to switch banks, which would unmap the code itself
+    RomBankFromRomx(u16),
+    /// `far_jp` never returns, so registers the allocator still holds can't be saved around it
+    FarJumpClobbers,
+    /// The helper needs the register for itself, but the allocator still holds it
+    RegisterInUse(GpRegister),
+    /// The helper needs CGB hardware, but the program targets [Model::Dmg](state::Model::Dmg)
+    CgbOnly,
+    /// LY and the PCM registers can only be read
+    ReadOnlyRegister(IoReg),
+    /// `value` sets bits the register ignores or only lets the hardware set
+    UnwritableBits { reg: IoReg, value: u8 },
+    /// The register doesn't exist on the model the program targets
+    UnavailableRegister(IoReg, Model),
 }
 
 impl Display for AssemblerError {
@@ -55,6 +86,54 @@ impl Display for AssemblerError {
     }
 }
 
+/// Errors from laying out a finished program into a ROM image
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    AssemblerErrors(Vec<AssemblerError>),
+    HeaderError(HeaderError),
+    /// Main code didn't fit in ROM0 below the constants
+    CodeTooBig { len: usize, max: usize },
+    /// A [BankedSection](cgb::BankedSection) didn't fit in its bank
+    SectionTooBig { bank: u16, len: usize },
+    /// The ROM size set in the header is smaller than the banks in use
+    RomTooSmall { size: usize, needed: usize },
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CodeTooBig { len, max } => write!(f, "CodeTooBig: Main code is {len} bytes, only {max} fit in ROM0"),
+            Self::SectionTooBig { bank, len } => write!(f, "SectionTooBig: Section in bank {bank} is {len} bytes"),
+            Self::RomTooSmall { size, needed } => write!(f, "RomTooSmall: Header says {size} bytes, need {needed}"),
+            _ => write!(f, "{self:?}"),
+        }
+    }
+}
+
+impl From<Vec<AssemblerError>> for BuildError {
+    fn from(value: Vec<AssemblerError>) -> Self {
+        Self::AssemblerErrors(value)
+    }
+}
+
+impl From<AssemblerError> for BuildError {
+    fn from(value: AssemblerError) -> Self {
+        Self::AssemblerErrors(vec![value])
+    }
+}
+
+impl From<HeaderError> for BuildError {
+    fn from(value: HeaderError) -> Self {
+        Self::HeaderError(value)
+    }
+}
+
+impl From<BuildError> for std::io::Error {
+    fn from(value: BuildError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, value.to_string())
+    }
+}
+
 impl ErrorTrait for AssemblerError {
     fn invalid_arg() -> Self where Self: Sized {
         Self::ArgumentError
@@ -1,8 +1,8 @@
 use std::{cell::RefCell, marker::PhantomData, ops::{Index, IndexMut}, rc::Rc};
 
-use crate::{cpu::{GpRegister, RegisterPair, SplitError}, memory::Addr};
+use crate::{cartridge::{BankedAddr, Mbc, ROMX_BASE, ROM_BANK_SHADOW, ROM_BANK_SIZE, SRAM_BANK_SIZE, SRAM_BASE}, cpu::{GpRegister, RegisterPair, SplitError}, memory::Addr};
 
-use super::{variables::{MemoryVariable, RawRegVariable, RegSelector, RegVariable}, Id, Variable};
+use super::{state::SystemState, symbols::{region_name, SymbolTable}, variables::{MemoryVariable, RawRegVariable, RegSelector, RegVariable}, Id, Variable};
 
 #[derive(Clone, Default, Debug, PartialEq, Eq)]
 pub struct GpRegisters {
@@ -392,17 +392,89 @@ impl AllocGroup {
         }
     }
 
+    /// Allocates `len` bytes starting at a multiple of `align`, skipping over whatever doesn't fit
+    pub fn alloc_aligned(&mut self, len: u16, align: u16) -> Result<Addr, ConstAllocError> {
+        let start = (self.offset + self.next).next_multiple_of(align) - self.offset;
+        if start > self.len {
+            Err(ConstAllocError::OutOfMemory)?
+        }
+
+        let next = self.next;
+        self.next = start;
+        self.alloc(len).inspect_err(|_| self.next = next)
+    }
+
     pub fn dealloc(&mut self, addr: Addr) -> Result<(), ConstAllocError> {
         // bumpy
         Ok(())
     }
 }
 
+impl AllocGroup {
+    /// An empty ROMX bank
+    pub fn romx() -> Self {
+        Self {
+            next: 0,
+            offset: ROMX_BASE,
+            len: ROM_BANK_SIZE as Addr,
+            used: 0,
+        }
+    }
+
+    /// An empty cartridge RAM bank
+    pub fn sram() -> Self {
+        Self {
+            next: 0,
+            offset: SRAM_BASE,
+            len: SRAM_BANK_SIZE as Addr,
+            used: 0,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ConstAllocator {
+    pub mbc: Mbc,
+    /// Constants in ROM0
     pub constants: AllocGroup,
+    /// ROMX banks that constants spilled into
+    pub rom_banks: Vec<(u16, AllocGroup)>,
+    /// Next ROMX bank that hasn't been handed out to constants or code
+    pub next_bank: u16,
     pub variables: AllocGroup,
+    pub hram: AllocGroup,
+    /// Cartridge RAM banks, in bank order
+    pub sram_banks: Vec<AllocGroup>,
     pub registers: Rc<RefCell<GpRegisters>>,
+    /// Names for everything allocated in memory, for the `.sym` file
+    pub symbols: SymbolTable,
+    pub state: SystemState,
+}
+
+impl ConstAllocator {
+    /// Number of ROM banks in use, including ROM0
+    pub fn bank_count(&self) -> u16 {
+        self.next_bank.max(2)
+    }
+
+    fn alloc_romx_const(&mut self, len: u16, align: u16) -> Result<BankedAddr, ConstAllocError> {
+        if len as usize > ROM_BANK_SIZE {
+            Err(ConstAllocError::OutOfMemory)?
+        }
+
+        if let Some((bank, group)) = self.rom_banks.last_mut() {
+            if let Ok(addr) = group.alloc_aligned(len, align) {
+                return Ok(BankedAddr { bank: *bank, addr });
+            }
+        }
+
+        let bank = self.alloc_bank()?;
+        let mut group = AllocGroup::romx();
+        let addr = group.alloc_aligned(len, align)?;
+        self.rom_banks.push((bank, group));
+
+        Ok(BankedAddr { bank, addr })
+    }
 }
 
 impl Default for ConstAllocator {
@@ -419,11 +491,25 @@ impl Default for ConstAllocator {
             len: 0x1000,
             used: 0,
         };
+        // the first HRAM byte is reserved for the ROM bank shadow, and 0xffff is IE
+        let hram = AllocGroup {
+            next: 0,
+            offset: ROM_BANK_SHADOW + 1,
+            len: 0xffff - (ROM_BANK_SHADOW + 1),
+            used: 0,
+        };
 
         Self {
+            mbc: Mbc::default(),
             constants,
+            rom_banks: Vec::new(),
+            next_bank: 1,
             variables,
+            hram,
+            sram_banks: Vec::new(),
             registers: Default::default(),
+            symbols: Default::default(),
+            state: Default::default(),
         }
     }
 }
@@ -431,7 +517,9 @@ impl Default for ConstAllocator {
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ConstAllocError {
     OutOfMemory,
+    OutOfBanks,
     OutOfRegisters,
+    NoCartridgeRam,
     TooBigForRegister,
 }
 
@@ -479,16 +567,109 @@ impl Allocator<ConstAllocError> for ConstAllocator {
         self.registers.borrow_mut().is_claimed(reg)
     }
 
-    fn alloc_const(&mut self, len: u16) -> Result<Addr, ConstAllocError> {
-        self.constants.alloc(len)
+    fn alloc_const(&mut self, len: u16) -> Result<BankedAddr, ConstAllocError> {
+        match self.constants.alloc(len) {
+            Ok(addr) => Ok(BankedAddr { bank: 0, addr }),
+            Err(ConstAllocError::OutOfMemory) => self.alloc_romx_const(len, 1),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn alloc_const_aligned(&mut self, len: u16, align: u16) -> Result<BankedAddr, ConstAllocError> {
+        match self.constants.alloc_aligned(len, align) {
+            Ok(addr) => Ok(BankedAddr { bank: 0, addr }),
+            Err(ConstAllocError::OutOfMemory) => self.alloc_romx_const(len, align),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn alloc_rom0_const(&mut self, len: u16) -> Result<BankedAddr, ConstAllocError> {
+        let addr = self.constants.alloc(len)?;
+        Ok(BankedAddr { bank: 0, addr })
+    }
+
+    fn alloc_bank(&mut self) -> Result<u16, ConstAllocError> {
+        if self.next_bank >= self.mbc.max_banks() {
+            Err(ConstAllocError::OutOfBanks)
+        } else {
+            let bank = self.next_bank;
+            self.next_bank += 1;
+
+            Ok(bank)
+        }
     }
 
     fn alloc_var(&mut self, len: u16) -> Result<Addr, ConstAllocError> {
-        self.variables.alloc(len)
+        let addr = self.variables.alloc(len)?;
+        self.symbols.add_default(addr.into(), region_name(addr.into()));
+
+        Ok(addr)
+    }
+
+    fn alloc_var_aligned(&mut self, len: u16, align: u16) -> Result<Addr, ConstAllocError> {
+        let addr = self.variables.alloc_aligned(len, align)?;
+        self.symbols.add_default(addr.into(), region_name(addr.into()));
+
+        Ok(addr)
+    }
+
+    fn alloc_hram(&mut self, len: u16) -> Result<Addr, ConstAllocError> {
+        let addr = self.hram.alloc(len)?;
+        self.symbols.add_default(addr.into(), region_name(addr.into()));
+
+        Ok(addr)
+    }
+
+    fn alloc_sram(&mut self, len: u16) -> Result<BankedAddr, ConstAllocError> {
+        if self.mbc.max_ram_banks() == 0 {
+            Err(ConstAllocError::NoCartridgeRam)?
+        }
+
+        if len as usize > SRAM_BANK_SIZE {
+            Err(ConstAllocError::OutOfMemory)?
+        }
+
+        if let Some(group) = self.sram_banks.last_mut() {
+            if let Ok(addr) = group.alloc(len) {
+                let addr = BankedAddr { bank: self.sram_banks.len() as u16 - 1, addr };
+                self.symbols.add_default(addr, region_name(addr));
+                return Ok(addr);
+            }
+        }
+
+        if self.sram_banks.len() as u16 >= self.mbc.max_ram_banks() {
+            Err(ConstAllocError::OutOfBanks)?
+        }
+
+        let mut group = AllocGroup::sram();
+        let addr = group.alloc(len)?;
+        self.sram_banks.push(group);
+
+        let addr = BankedAddr { bank: self.sram_banks.len() as u16 - 1, addr };
+        self.symbols.add_default(addr, region_name(addr));
+
+        Ok(addr)
+    }
+
+    fn mbc(&self) -> Mbc {
+        self.mbc
+    }
+
+    fn state(&self) -> &SystemState {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut SystemState {
+        &mut self.state
+    }
+
+    fn symbols_mut(&mut self) -> &mut SymbolTable {
+        &mut self.symbols
     }
 
     fn dealloc_var(&mut self, var: Variable) -> Result<&mut Self, ConstAllocError> {
         match var {
+            Variable::Memory(MemoryVariable { addr, .. }) if addr >= self.hram.offset => { self.hram.dealloc(addr)?; },
             Variable::Memory(MemoryVariable { addr, .. }) => { self.variables.dealloc(addr)?; },
             Variable::Reg(var) => match var {
                 RegVariable::Rc(var) => match var.inner {
@@ -554,8 +735,27 @@ pub trait Allocator<AllocError>: std::fmt::Debug
     fn claim_reg_pair(&mut self, reg: RegisterPair, id: Id) -> RcRegisterPair;
     /// Returns true if the selected register is unallocated
     fn reg_is_used(&self, reg: RegSelector) -> bool;
-    fn alloc_const(&mut self, len: u16) -> Result<Addr, AllocError>;
+    /// Allocates a constant in ROM0, spilling into ROMX banks when ROM0 is full
+    fn alloc_const(&mut self, len: u16) -> Result<BankedAddr, AllocError>;
+    /// Like [Allocator::alloc_const], but starting at a multiple of `align`
+    fn alloc_const_aligned(&mut self, len: u16, align: u16) -> Result<BankedAddr, AllocError>;
+    /// Like [Allocator::alloc_const], but failing when ROM0 is full instead of spilling into ROMX
+    fn alloc_rom0_const(&mut self, len: u16) -> Result<BankedAddr, AllocError>;
+    /// Reserves a whole ROMX bank
+    fn alloc_bank(&mut self) -> Result<u16, AllocError>;
     fn alloc_var(&mut self, len: u16) -> Result<Addr, AllocError>;
+    /// Allocates a variable in WRAM starting at a multiple of `align`
+    fn alloc_var_aligned(&mut self, len: u16, align: u16) -> Result<Addr, AllocError>;
+    /// Allocates `len` bytes of HRAM, which can be reached with the shorter `ldh` instructions
+    fn alloc_hram(&mut self, len: u16) -> Result<Addr, AllocError>;
+    /// Allocates `len` bytes of cartridge RAM, moving on to the next bank when the current one is full
+    fn alloc_sram(&mut self, len: u16) -> Result<BankedAddr, AllocError>;
+    fn mbc(&self) -> Mbc;
+    /// Hardware state at the end of the code emitted so far
+    fn state(&self) -> &SystemState;
+    fn state_mut(&mut self) -> &mut SystemState;
+    /// Names for everything allocated in memory, for the `.sym` file
+    fn symbols_mut(&mut self) -> &mut SymbolTable;
     fn dealloc_var(&mut self, var: Variable) -> Result<&mut Self, AllocError>;
 }
 
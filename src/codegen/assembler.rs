@@ -1,6 +1,6 @@
-use crate::{codegen::block::BlockTrait, cpu::{instructions::{Bit, Instruction, PrefixInstruction}, Condition, GpRegister, IndirectPair, RegisterPair, SplitError, StackPair}, memory::{Addr, IoReg}, ppu::{objects::{Sprite, SpriteIdx}, palettes::{CgbPalette, Color, PaletteSelector}, tiles::{Tile, TileIdx, Tilemap}, TiledataSelector, TilemapSelector}};
+use crate::{apu::{SoundEffect, APU_ENABLE, SFX_END}, cartridge::{Mbc, FAR_CALL, FAR_JP, MBC1_MODE_SELECT, RAM_BANK_SELECT, RAM_ENABLE, RAM_ENABLE_VALUE, ROM_BANK_SELECT, ROM_BANK_SHADOW}, codegen::block::BlockTrait, cpu::{instructions::{Bit, Instruction, PrefixInstruction}, interrupts::Interrupts, Condition, CpuFlag, CpuSpeed, GpRegister, IndirectPair, RegisterPair, SplitError, StackPair}, joypad::{ButtonEdge, Buttons, Joypad, SELECT_BUTTONS, SELECT_DPAD, SELECT_NONE}, memory::{Addr, IoReg, OAM_SIZE, VRAM_BASE, VRAM_DMA_BLOCK, VRAM_DMA_MAX_LEN, VRAM_SIZE, WAVE_RAM_BASE, WAVE_RAM_SIZE}, timer::{TimerConfig, TimerControl}, ppu::{fade::{FadeTarget, PaletteFade, FADE_END}, import::image::{ImportedImage, BANK_TILES}, lcd::{LcdcFlags, StatFlags}, objects::{Metasprite, ObjAttributeFlags, ObjSize, Sprite, SpriteIdx}, palettes::{CgbPalette, Color, DmgPalette, DmgPaletteKind, PaletteKind, PaletteSelector}, tiles::{AttributeMap, Tile, TileAttributes, TileIdx, Tilemap}, TiledataSelector, TilemapSelector, VramBank}};
 
-use super::{allocator::{AllocErrorTrait, ConstAllocError}, block::basic_block::BasicBlock, meta_instr::{MetaInstructionTrait, VarOrConst}, variables::{Constant, RawRegVariable, RawVariable, StoredConstant, Variabler}, AssemblerError, Id, IdInner, LoopBlock, LoopCondition, Variable};
+use super::{allocator::{AllocErrorTrait, Allocator, ConstAllocError}, block::basic_block::BasicBlock, meta_instr::{MetaInstructionTrait, VarOrConst}, state::{Fade, LcdState, Model, OamDma, Sound}, variables::{Constant, MemoryVariable, RawRegVariable, RawVariable, SramGuard, SramVariable, StoredConstant, Variabler}, AssemblerError, Id, IdInner, LoopBlock, LoopCondition, Variable};
 
 pub trait Assembler<Meta>
         where Meta: Clone + std::fmt::Debug + MetaInstructionTrait {
@@ -106,6 +106,22 @@ pub trait Assembler<Meta>
         self
     }
 
+    /// `cp a, imm`
+    /// 
+    /// Subtract `imm` from `a` without storing the result (still changes flags)
+    fn cp_imm(&mut self, imm: u8) -> &mut Self {
+        self.push_instruction(Instruction::CpImm(imm));
+        self
+    }
+
+    /// `add a, imm`
+    /// 
+    /// Add `imm` to `a`
+    fn add_imm(&mut self, imm: u8) -> &mut Self {
+        self.push_instruction(Instruction::AddImm(imm));
+        self
+    }
+
     /// `pop rr`
     /// 
     /// Pops 2 bytes off the stack into `rr`
@@ -122,6 +138,14 @@ pub trait Assembler<Meta>
         self
     }
 
+    /// Marks the current position with `id`
+    /// 
+    /// Emits nothing, but shows up in the `.sym` file
+    fn label(&mut self, id: Id) -> &mut Self {
+        self.push_instruction(Instruction::Label(id));
+        self
+    }
+
     /// `push rr`
     /// 
     /// Pushes `rr` onto the stack
@@ -205,6 +229,133 @@ pub trait Assembler<Meta>
         self
     }
 
+    /// `swap reg`
+    /// 
+    /// Swaps the upper and lower nibbles of `reg`
+    fn swap<T>(&mut self, reg: T) -> &mut Self
+            where T: Into<GpRegister> {
+        self.push_instruction(PrefixInstruction::Swap(reg.into()).into());
+        self
+    }
+
+    /// `add a, r`
+    fn add<T>(&mut self, reg: T) -> &mut Self
+            where T: Into<GpRegister> {
+        self.push_instruction(Instruction::Add(reg.into()));
+        self
+    }
+
+    /// `and a, r`
+    fn and<T>(&mut self, reg: T) -> &mut Self
+            where T: Into<GpRegister> {
+        self.push_instruction(Instruction::And(reg.into()));
+        self
+    }
+
+    /// `and a, imm`
+    fn and_imm(&mut self, imm: u8) -> &mut Self {
+        self.push_instruction(Instruction::AndImm(imm));
+        self
+    }
+
+    /// `xor a, r`
+    fn xor<T>(&mut self, reg: T) -> &mut Self
+            where T: Into<GpRegister> {
+        self.push_instruction(Instruction::Xor(reg.into()));
+        self
+    }
+
+    /// `or a, r`
+    fn or<T>(&mut self, reg: T) -> &mut Self
+            where T: Into<GpRegister> {
+        self.push_instruction(Instruction::Or(reg.into()));
+        self
+    }
+
+    /// `or a, imm`
+    fn or_imm(&mut self, imm: u8) -> &mut Self {
+        self.push_instruction(Instruction::OrImm(imm));
+        self
+    }
+
+    /// `cpl`
+    /// 
+    /// Flips every bit of `a`
+    fn cpl(&mut self) -> &mut Self {
+        self.push_instruction(Instruction::Cpl);
+        self
+    }
+
+    /// `call cc, a16`
+    /// 
+    /// Pushes the address of the next instruction and jumps to `addr` if `condition` is true
+    fn call(&mut self, condition: Condition, addr: Addr) -> &mut Self {
+        self.push_instruction(Instruction::Call(condition, addr));
+        self
+    }
+
+    /// `ret cc`
+    /// 
+    /// Pops the return address off the stack and jumps to it if `condition` is true
+    fn ret(&mut self, condition: Condition) -> &mut Self {
+        self.push_instruction(Instruction::Ret(condition));
+        self
+    }
+
+    /// `jp hl`
+    /// 
+    /// Jump to the address in `hl`
+    fn jp_hl(&mut self) -> &mut Self {
+        self.push_instruction(Instruction::JpHl);
+        self
+    }
+
+    /// `reti`
+    /// 
+    /// Returns from an interrupt handler, enabling interrupts again
+    fn reti(&mut self) -> &mut Self {
+        self.push_instruction(Instruction::Reti);
+        self
+    }
+
+    /// `ei`
+    /// 
+    /// Enables interrupts (sets IME) after the next instruction
+    fn ei(&mut self) -> &mut Self {
+        self.push_instruction(Instruction::Ei);
+        self
+    }
+
+    /// `di`
+    /// 
+    /// Disables interrupts (clears IME)
+    fn di(&mut self) -> &mut Self {
+        self.push_instruction(Instruction::Di);
+        self
+    }
+
+    /// `halt`
+    /// 
+    /// Stops the CPU until an interrupt is pending
+    fn halt(&mut self) -> &mut Self {
+        self.push_instruction(Instruction::Halt);
+        self
+    }
+
+    /// `stop`
+    /// 
+    /// Stops the CPU and the LCD until a button is pressed, or switches CPU speed if KEY1 is armed
+    fn stop(&mut self) -> &mut Self {
+        self.push_instruction(Instruction::Stop);
+        self
+    }
+
+    /// `nop`
+    fn nop(&mut self) -> &mut Self {
+        self.push_instruction(Instruction::Nop);
+        self
+    }
+
     /// Metadata tag for assembler usage
     fn meta(&mut self, meta: Meta) -> &mut Self {
         self.push_instruction(Instruction::Meta(meta));
@@ -218,6 +369,8 @@ pub trait BlockAssembler<Meta>
     fn basic_block(&mut self) -> &mut BasicBlock<Meta>;
     /// [LoopBlock] builder
     fn loop_block(&mut self, condition: LoopCondition) -> &mut LoopBlock<Meta>;
+    /// ROM bank the code being built lives in, see [BasicBlock::rom_bank]
+    fn rom_bank(&self) -> u16;
 }
 
 pub trait MacroAssembler<Meta, Error, AllocError>: Assembler<Meta> + Variabler<Meta, Error, AllocError> + BlockAssembler<Meta>
@@ -225,130 +378,1075 @@ pub trait MacroAssembler<Meta, Error, AllocError>: Assembler<Meta> + Variabler<M
             Error: Clone + std::fmt::Debug + From<SplitError> + From<AllocError> + From<AssemblerError> + From<ConstAllocError> + ErrorTrait, // TODO: Not this
             AllocError: Clone + std::fmt::Debug + Into<Error> + AllocErrorTrait, {
     fn new_stored_const(&mut self, data: &[u8]) -> Result<StoredConstant, Error>;
+    /// Like [MacroAssembler::new_stored_const], but starting at a multiple of `align`
+    fn new_stored_const_aligned(&mut self, data: &[u8], align: u16) -> Result<StoredConstant, Error>;
+    /// Like [MacroAssembler::new_stored_const], but erroring when ROM0 is full instead of spilling into ROMX
+    fn new_rom0_const(&mut self, data: &[u8]) -> Result<StoredConstant, Error>;
     fn new_inline_const_r8(&mut self, data: u8) -> Constant;
     fn new_inline_const_r16(&mut self, data: u16) -> Constant;
     fn evaluate_meta(&mut self) -> Result<(), Error>;
     fn gather_consts(&mut self) -> Vec<(Constant, Vec<u8>)>;
 
+    /// Writes background palette `palette`
     fn set_palette(&mut self, palette: CgbPalette, colors: [Color; 4]) -> Result<(), Error> {
-        let colors: Vec<u8> = colors.iter().flat_map(|color| color.0.to_be_bytes()).collect();
-        let addr = self.new_stored_const(&colors)?;
+        self.write_palette(PaletteKind::Background, palette, colors)
+    }
 
-        let reg_hl = self.claim_reg_pair(RegisterPair::HL, Id::Unset);
-        let reg_a = self.claim_reg(GpRegister::A, Id::Unset);
+    /// Writes object palette `palette`, used by sprites with that [ObjAttributes::cgb_palette](crate::ppu::objects::ObjAttributes)
+    /// 
+    /// Color 0 is transparent for objects, so it's written but never shows up
+    fn set_obj_palette(&mut self, palette: CgbPalette, colors: [Color; 4]) -> Result<(), Error> {
+        self.write_palette(PaletteKind::Object, palette, colors)
+    }
 
-        let block = self.basic_block().open(|block| {
-            block.ld_r16_imm(reg_hl.inner, addr.addr);
-            block.ld_r8_imm(reg_a.inner, PaletteSelector::new(true, palette).into());
-            block.ldh_from_a(IoReg::Bcps.into());
-        });
+    /// Writes `colors` into `palette` of the given kind, through its spec and data registers
+    /// 
+    /// Palette memory can't be accessed while the PPU is drawing, so this needs the LCD off or VBlank.
+    /// CGB only, see [MacroAssembler::set_dmg_palette] for DMG
+    fn write_palette(&mut self, kind: PaletteKind, palette: CgbPalette, colors: [Color; 4]) -> Result<(), Error> {
+        self.require_cgb()?;
+        let bytes: Vec<u8> = colors.iter().flat_map(Color::to_bytes).collect();
+        let addr = self.new_stored_const(&bytes)?;
+
+        self.in_macro("write_palette", |this| {
+            let reg_hl = this.claim_reg_pair(RegisterPair::HL, Id::Unset);
+            let reg_a = this.claim_reg(GpRegister::A, Id::Unset);
+
+            this.push_rom_bank(addr.bank)?;
+
+            let block = this.basic_block().open(|block| {
+                block.ld_r16_imm(reg_hl.inner, addr.addr);
+                block.ld_r8_imm(reg_a.inner, PaletteSelector::new(true, palette).into());
+                block.ldh_from_a(kind.spec_reg().into());
+            });
         
-        let counter = block.init_var8(colors.len() as u8)?.into_raw();
-        block.loop_block(LoopCondition::Countdown { counter: counter.clone(), end: 0 }).open(|block| {
-            block.ld_a_from_r16(IndirectPair::HLInc).ldh_from_a(IoReg::Bcpd.into());
-        });
+            let counter = block.init_var8(bytes.len() as u8)?.into_raw();
+            block.loop_block(LoopCondition::Countdown { counter: counter.clone(), end: 0 }).open(|block| {
+                block.ld_a_from_r16(IndirectPair::HLInc).ldh_from_a(kind.data_reg().into());
+            });
+
+            this.dealloc_var(counter.into())?;
+            this.pop_rom_bank(addr.bank);
 
-        self.dealloc_var(counter.into())?;
+            Ok(())
+        })?;
 
+        self.allocator().borrow_mut().state_mut().palettes.set(kind, palette, colors);
         Ok(())
     }
 
     fn copy(&mut self, src: Addr, dest: Addr, len: u16) -> Result<(), Error> {
-        let reg_hl = RegisterPair::HL;
-        let hl_stacked = if self.reg_is_used(RegisterPair::HL) {
-            self.push(StackPair::HL);
-            true
-        } else {
-            false
-        };
+        self.in_macro("copy", |this| {
+            let reg_hl = RegisterPair::HL;
+            let hl_stacked = if this.reg_is_used(RegisterPair::HL) {
+                this.push(StackPair::HL);
+                true
+            } else {
+                false
+            };
 
-        let reg_a = GpRegister::A;
-        let af_stacked = if self.reg_is_used(GpRegister::A) {
-            self.push(StackPair::AF);
-            true
-        } else {
-            false
-        };
+            let reg_a = GpRegister::A;
+            let af_stacked = if this.reg_is_used(GpRegister::A) {
+                this.push(StackPair::AF);
+                true
+            } else {
+                false
+            };
         
-        self.ld_r16_imm(reg_hl, dest);
+            this.ld_r16_imm(reg_hl, dest);
         
-        let hl_var: RawVariable = RawRegVariable::from(reg_hl).into();
-        let mut data_pointer = self.init_var16(src)?;
-
-        let block = (|| {
-            if let Ok(len) = len.try_into() {
-                if let Ok(reg) = self.alloc_reg() {
-                    self.ld_r8_imm(&reg, len);
-                    let counter: RawVariable = RawRegVariable::from(reg.inner).into();
-                    return self.loop_block(LoopCondition::Countdown { counter, end: 0 });
+            let hl_var: RawVariable = RawRegVariable::from(reg_hl).into();
+            let data_pointer = this.init_var16(src)?;
+
+            let block = (|| {
+                if let Ok(len) = len.try_into() {
+                    if let Ok(reg) = this.alloc_reg() {
+                        this.ld_r8_imm(&reg, len);
+                        let counter: RawVariable = RawRegVariable::from(reg.inner).into();
+                        return this.loop_block(LoopCondition::Countdown { counter, end: 0 });
+                    }
                 }
+                this.loop_block(LoopCondition::Countup{ counter: hl_var , end: dest + len })
+            })();
+
+            block.ld_a_from_var_ind(&data_pointer)?
+                .ld_a_to_r16(IndirectPair::HLInc)
+                .inc_var(&data_pointer)?;
+
+            if hl_stacked { this.pop(StackPair::HL); }
+            if af_stacked { this.pop(StackPair::AF); }
+
+            Ok(())
+        })
+    }
+
+    /// Sets one of the DMG palette registers, mapping each color index to a shade
+    /// 
+    /// Works on both models, though CGB only looks at them when running a DMG game
+    fn set_dmg_palette(&mut self, kind: DmgPaletteKind, palette: DmgPalette) -> Result<(), Error> {
+        self.set_ioreg(kind.reg(), palette.into())?;
+        self.allocator().borrow_mut().state_mut().palettes.set_dmg(kind, palette);
+
+        Ok(())
+    }
+
+    /// Copies `len` bytes from `src` into VRAM at `dest`, through general purpose DMA on CGB when both are aligned to
+    /// [VRAM_DMA_BLOCK] and through [MacroAssembler::copy] otherwise
+    /// 
+    /// Like any VRAM write, this needs the LCD off or the PPU outside mode 3 for the whole transfer
+    fn copy_to_vram(&mut self, src: Addr, dest: Addr, len: u16) -> Result<(), Error> {
+        if vram_dma_args_valid(src, dest, len) && self.require_cgb().is_ok() {
+            self.general_dma(src, dest, len)
+        } else {
+            // copy only counts 8-bit lengths down
+            for offset in (0..len).step_by(u8::MAX as usize) {
+                self.copy(src + offset, dest + offset, (len - offset).min(u8::MAX as u16))?;
             }
-            self.loop_block(LoopCondition::Countup{ counter: hl_var , end: dest + len })
-        })();
 
-        block.ld_a_from_var_ind(&mut data_pointer)?
-            .ld_a_to_r16(IndirectPair::HLInc)
-            .inc_var(&mut data_pointer)?;
+            Ok(())
+        }
+    }
 
-        if hl_stacked { self.pop(StackPair::HL); }
-        if af_stacked { self.pop(StackPair::AF); }
+    /// Copies `len` bytes from `src` into VRAM at `dest` with general purpose DMA, halting the CPU until it's done
+    /// 
+    /// `src` and `dest` must be aligned to [VRAM_DMA_BLOCK], `len` a multiple of it up to [VRAM_DMA_MAX_LEN].
+    /// `src` can't be in VRAM or echo RAM
+    fn general_dma(&mut self, src: Addr, dest: Addr, len: u16) -> Result<(), Error> {
+        self.in_macro("general_dma", |this| this.vram_dma(src, dest, len, false))
+    }
+
+    /// Starts copying `len` bytes from `src` into VRAM at `dest`, one [VRAM_DMA_BLOCK] per HBlank, and returns immediately
+    /// 
+    /// Safe while the LCD is on, since each block goes in while the PPU leaves VRAM alone. Nothing moves while the LCD is off.
+    /// Same restrictions on the arguments as [MacroAssembler::general_dma]. [MacroAssembler::wait_hblank_dma] waits for the end
+    fn hblank_dma(&mut self, src: Addr, dest: Addr, len: u16) -> Result<(), Error> {
+        self.in_macro("hblank_dma", |this| this.vram_dma(src, dest, len, true))
+    }
+
+    /// Loads the VRAM DMA registers, see [MacroAssembler::general_dma] and [MacroAssembler::hblank_dma]
+    fn vram_dma(&mut self, src: Addr, dest: Addr, len: u16, hblank: bool) -> Result<(), Error> {
+        self.require_cgb()?;
+        if !vram_dma_args_valid(src, dest, len) {
+            Err(Error::invalid_arg())?
+        }
+
+        let dest = dest - VRAM_BASE;
+        let blocks = (len / VRAM_DMA_BLOCK - 1) as u8;
+
+        self.set_ioreg(IoReg::Hdma1, (src >> 8) as u8)?;
+        self.set_ioreg(IoReg::Hdma2, src as u8)?;
+        self.set_ioreg(IoReg::Hdma3, (dest >> 8) as u8)?;
+        self.set_ioreg(IoReg::Hdma4, dest as u8)?;
+        self.set_ioreg(IoReg::Hdma5, (hblank as u8) << 7 | blocks)?;
 
         Ok(())
     }
 
+    /// Spins until the running [MacroAssembler::hblank_dma] is done, HDMA5 reads 0xff once it is
+    /// 
+    /// Clobbers `a`
+    fn wait_hblank_dma(&mut self) {
+        self.meta(Meta::macro_call("wait_hblank_dma"))
+            .ldh_to_a(IoReg::Hdma5.into())
+            .inc_r8(GpRegister::A)
+            .jr(CpuFlag::NZ, -5)
+            .meta(Meta::end());
+    }
+
     fn write_tile_data(&mut self, area: TiledataSelector, idx: TileIdx, data: &Tile) -> Result<(), Error> {
-        let src = self.new_stored_const(&data.as_bytes())?;
-        let dest = area.from_idx(idx);
+        self.write_tile_data_in_bank(VramBank::_0, area, idx, data)
+    }
 
-        self.basic_block().copy(src.addr, dest, Tile::MEM_SIZE as u16)?;
+    /// Like [MacroAssembler::write_tile_data], but into VRAM bank `bank`
+    /// 
+    /// Tiles in bank 1 are used by BG map entries with [TileAttributeFlags::BANK1](crate::ppu::tiles::TileAttributeFlags::BANK1) set
+    fn write_tile_data_in_bank(&mut self, bank: VramBank, area: TiledataSelector, idx: TileIdx, data: &Tile) -> Result<(), Error> {
+        self.use_vram_bank(bank)?;
+        self.in_macro("write_tile_data", |this| {
+            let src = this.new_stored_const_aligned(&data.as_bytes(), VRAM_DMA_BLOCK)?;
+            let dest = area.from_idx(idx);
+
+            this.push_rom_bank(src.bank)?;
+            this.basic_block().copy_to_vram(src.addr, dest, Tile::MEM_SIZE as u16)?;
+            this.pop_rom_bank(src.bank);
+
+            Ok(())
+        })
+    }
 
-        Ok(())
+    /// Writes `tiles` into VRAM bank `bank` from index `first` on, as a single constant
+    /// 
+    /// Errors if the tiles run past index 255
+    fn write_tiles_in_bank(&mut self, bank: VramBank, area: TiledataSelector, first: TileIdx, tiles: &[Tile]) -> Result<(), Error> {
+        let first = first as usize;
+        let end = first + tiles.len();
+        if end > BANK_TILES {
+            Err(Error::invalid_arg())?
+        }
+        if tiles.is_empty() {
+            return Ok(());
+        }
+
+        self.use_vram_bank(bank)?;
+        self.in_macro("write_tiles", |this| {
+            let data: Vec<u8> = tiles.iter().flat_map(Tile::as_bytes).collect();
+            let src = this.new_stored_const_aligned(&data, VRAM_DMA_BLOCK)?;
+
+            this.push_rom_bank(src.bank)?;
+            let block = this.basic_block();
+            let mut idx = first;
+            while idx < end {
+                // 128 tiles fill a whole VRAM DMA, and Tiledata9000 wraps around to 0x8800 at tile 128
+                let chunk_end = end.min((idx / 128 + 1) * 128);
+                let offset = ((idx - first) * Tile::MEM_SIZE) as u16;
+                let len = ((chunk_end - idx) * Tile::MEM_SIZE) as u16;
+                block.copy_to_vram(src.addr + offset, area.from_idx(idx as u8), len)?;
+                idx = chunk_end;
+            }
+            this.pop_rom_bank(src.bank);
+
+            Ok(())
+        })
     }
 
-    fn set_tilemap(&mut self, tilemap: TilemapSelector, data: Tilemap) -> Result<(), Error> {
-        let block = self.basic_block();
-        let addr = block.new_stored_const((&data).into())?;
-        block.copy(addr.addr, tilemap.base(), data.len() as u16)?;
+    /// Uploads an [ImportedImage]: its palettes, its tiles into `area`, and its map into `tilemap`
+    /// 
+    /// On DMG the background palette is set to [DmgPalette::DEFAULT], which the image's palette is sorted to match.
+    /// Errors if the image was imported for CGB but the program targets DMG. Like any VRAM write, this needs the LCD off
+    fn load_image(&mut self, image: &ImportedImage, area: TiledataSelector, tilemap: TilemapSelector) -> Result<(), Error> {
+        if image.model == Model::Cgb {
+            self.require_cgb()?;
+        }
 
-        Ok(())
+        self.in_macro("load_image", |this| {
+            let model = this.allocator().borrow().state().model;
+            match model {
+                Model::Cgb => for (&palette, &colors) in CgbPalette::ALL.iter().zip(&image.palettes) {
+                    this.set_palette(palette, colors)?;
+                },
+                Model::Dmg => this.set_dmg_palette(DmgPaletteKind::Background, DmgPalette::DEFAULT)?,
+            }
+
+            let (bank0, bank1) = image.tiles.split_at(image.tiles.len().min(BANK_TILES));
+            this.write_tiles_in_bank(VramBank::_0, area, 0, bank0)?;
+            this.write_tiles_in_bank(VramBank::_1, area, 0, bank1)?;
+            this.set_tilemap(tilemap, image.tilemap)?;
+
+            if model == Model::Cgb {
+                this.set_attribute_map(tilemap, &image.attributes)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn set_tilemap(&mut self, tilemap: TilemapSelector, data: Tilemap) -> Result<(), Error> {
+        self.use_vram_bank(VramBank::_0)?;
+        self.in_macro("set_tilemap", |this| {
+            let block = this.basic_block();
+            let addr = block.new_stored_const_aligned((&data).into(), VRAM_DMA_BLOCK)?;
+            block.push_rom_bank(addr.bank)?;
+            block.copy_to_vram(addr.addr, tilemap.base(), data.len() as u16)?;
+            block.pop_rom_bank(addr.bank);
+
+            Ok(())
+        })
     }
 
     /// `tile` is the tile in the map to set, `data` is the tile data to set it to
     fn set_tile(&mut self, tilemap: TilemapSelector, tile: TileIdx, data: TileIdx) -> Result<(), Error> {
-        let src = self.init_var8(data)?;
-        let dest = tilemap.from_idx(tile);
-        self.ld_var_to_ind(&src, dest)?;
+        self.use_vram_bank(VramBank::_0)?;
+        self.in_macro("set_tile", |this| {
+            let src = this.init_var8(data)?;
+            let dest = tilemap.from_idx(tile);
+            this.ld_var_to_ind(&src, dest)?;
+            Ok(())
+        })
+    }
+
+    /// Copies `data` into the attribute half of `tilemap` in VRAM bank 1, leaving bank 1 selected
+    fn set_attribute_map(&mut self, tilemap: TilemapSelector, data: &AttributeMap) -> Result<(), Error> {
+        self.use_vram_bank(VramBank::_1)?;
+        self.in_macro("set_attribute_map", |this| {
+            let block = this.basic_block();
+            let addr = block.new_stored_const_aligned(&data.as_bytes(), VRAM_DMA_BLOCK)?;
+            block.push_rom_bank(addr.bank)?;
+            block.copy_to_vram(addr.addr, tilemap.base(), data.len() as u16)?;
+            block.pop_rom_bank(addr.bank);
+
+            Ok(())
+        })
+    }
+
+    /// Sets the attributes of entry `tile` in `tilemap`, leaving VRAM bank 1 selected
+    fn set_tile_attributes(&mut self, tilemap: TilemapSelector, tile: TileIdx, attributes: TileAttributes) -> Result<(), Error> {
+        self.use_vram_bank(VramBank::_1)?;
+        self.in_macro("set_tile_attributes", |this| this.store_byte(tilemap.from_idx(tile), attributes.into()))
+    }
+
+    /// Maps VRAM bank `bank` into 0x8000-0x9fff. CGB only
+    fn set_vram_bank(&mut self, bank: VramBank) -> Result<(), Error> {
+        self.require_cgb()?;
+        self.set_ioreg(IoReg::Vbk, bank as u8)
+    }
+
+    /// Like [MacroAssembler::set_vram_bank], but emits nothing if `bank` is already selected
+    fn use_vram_bank(&mut self, bank: VramBank) -> Result<(), Error> {
+        if self.allocator().borrow().state().vram_bank != bank {
+            self.set_vram_bank(bank)?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs `inner` between a `name` listing marker and its end, closing the marker even if `inner` fails
+    fn in_macro<T, F>(&mut self, name: &'static str, inner: F) -> Result<T, Error>
+            where F: FnOnce(&mut Self) -> Result<T, Error> {
+        self.meta(Meta::macro_call(name));
+        let out = inner(self);
+        self.meta(Meta::end());
+
+        out
+    }
+
+    /// Errors with [AssemblerError::CgbOnly] when building for [Model::Dmg]
+    fn require_cgb(&self) -> Result<(), Error> {
+        match self.allocator().borrow().state().model {
+            Model::Cgb => Ok(()),
+            Model::Dmg => Err(AssemblerError::CgbOnly.into()),
+        }
+    }
+
+    /// Writes `sprite` into the shadow OAM, it shows up on screen after the next [MacroAssembler::oam_dma]
+    /// 
+    /// Errors if [MacroAssembler::init_oam_dma] hasn't been called yet
+    fn set_sprite(&mut self, sprite: Sprite, idx: SpriteIdx) -> Result<(), Error> {
+        let oam_dma = self.allocator().borrow().state().oam_dma.ok_or(AssemblerError::OamDmaUninitialized)?;
+        let addr = oam_dma.shadow + idx as Addr * 4;
+
+        self.in_macro("set_sprite", |this| {
+            for (offset, byte) in sprite.as_bytes().into_iter().enumerate() {
+                this.store_byte(addr + offset as Addr, byte)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Writes `metasprite` into the shadow OAM starting at sprite `first`, with its origin at the runtime position in `x` and `y`
+    /// 
+    /// `x` and `y` must be 8-bit and can't live in `a`. `flip` is applied at compile time for the size last passed to
+    /// [MacroAssembler::set_obj_size], see [Metasprite::flipped]
+    fn set_metasprite(&mut self, metasprite: &Metasprite, first: SpriteIdx, x: &Variable, y: &Variable, flip: ObjAttributeFlags) -> Result<(), Error> {
+        let oam_dma = self.allocator().borrow().state().oam_dma.ok_or(AssemblerError::OamDmaUninitialized)?;
+        if first as usize + metasprite.len() > 40 {
+            Err(Error::invalid_arg())?
+        }
+
+        if !var_is_r8(x) || !var_is_r8(y) || var_in_a(x) || var_in_a(y) {
+            Err(Error::invalid_arg())?
+        }
+
+        let size = self.allocator().borrow().state().obj_size;
+        let metasprite = metasprite.flipped(flip, size);
+        let af_stacked = self.reg_is_used(GpRegister::A);
+
+        self.in_macro("set_metasprite", |this| {
+            if af_stacked { this.push(StackPair::AF); }
+            for (idx, entry) in metasprite.entries.iter().enumerate() {
+                let addr = oam_dma.shadow + (first as Addr + idx as Addr) * 4;
+
+                this.ld_a_from_var(y)?
+                    .add_imm(entry.dy as u8)
+                    .ld_a_to_ind(addr)
+                    .ld_a_from_var(x)?
+                    .add_imm(entry.dx as u8)
+                    .ld_a_to_ind(addr + 1);
+                this.store_byte(addr + 2, entry.tile)?;
+                this.store_byte(addr + 3, entry.attr.into())?;
+            }
+            if af_stacked { this.pop(StackPair::AF); }
+
+            Ok(())
+        })
+    }
+
+    /// Allocates and clears the joypad variables in WRAM, see [Joypad]
+    /// 
+    /// Call this once at startup, before any [MacroAssembler::poll_joypad]
+    fn init_joypad(&mut self) -> Result<Joypad, Error> {
+        let joypad = Joypad {
+            current: self.alloc_var(1)?,
+            pressed: self.alloc_var(1)?,
+            released: self.alloc_var(1)?,
+        };
+
+        self.in_macro("init_joypad", |this| {
+            for addr in [joypad.current, joypad.pressed, joypad.released] {
+                this.store_byte(addr, 0)?;
+            }
+
+            Ok(())
+        })?;
+
+        let allocator = self.allocator();
+        let mut allocator = allocator.borrow_mut();
+        allocator.symbols_mut().rename(joypad.current.into(), "joypad_current");
+        allocator.symbols_mut().rename(joypad.pressed.into(), "joypad_pressed");
+        allocator.symbols_mut().rename(joypad.released.into(), "joypad_released");
+        allocator.state_mut().joypad = Some(joypad);
+
+        Ok(joypad)
+    }
+
+    /// Reads the joypad through P1, updating the held, pressed and released variables
+    /// 
+    /// Call this once per frame, pressed and released are relative to the previous poll
+    fn poll_joypad(&mut self) -> Result<(), Error> {
+        let joypad = self.allocator().borrow().state().joypad.ok_or(AssemblerError::JoypadUninitialized)?;
+        let p1: u8 = IoReg::P1.into();
+
+        self.meta(Meta::macro_call("poll_joypad"))
+            .push(StackPair::AF)
+            .push(StackPair::BC);
+
+        // buttons in the low nibble of `b`, the lines need a few reads to settle after selecting
+        self.ld_r8_imm(GpRegister::A, SELECT_BUTTONS)
+            .ldh_from_a(p1)
+            .ldh_to_a(p1)
+            .ldh_to_a(p1)
+            .ldh_to_a(p1)
+            .or_imm(0xf0)
+            .ld_r8_from_r8(GpRegister::B, GpRegister::A);
+
+        // d-pad in the high nibble, then flip everything so 1 means down
+        self.ld_r8_imm(GpRegister::A, SELECT_DPAD)
+            .ldh_from_a(p1)
+            .ldh_to_a(p1)
+            .ldh_to_a(p1)
+            .ldh_to_a(p1)
+            .or_imm(0xf0)
+            .swap(GpRegister::A)
+            .xor(GpRegister::B)
+            .ld_r8_from_r8(GpRegister::B, GpRegister::A)
+            .ld_r8_imm(GpRegister::A, SELECT_NONE)
+            .ldh_from_a(p1);
+
+        // `b` = now, `c` = last poll
+        self.ld_a_from_ind(joypad.current)
+            .ld_r8_from_r8(GpRegister::C, GpRegister::A)
+            .ld_r8_from_r8(GpRegister::A, GpRegister::B)
+            .ld_a_to_ind(joypad.current)
+            // pressed = now & !last
+            .ld_r8_from_r8(GpRegister::A, GpRegister::C)
+            .cpl()
+            .and(GpRegister::B)
+            .ld_a_to_ind(joypad.pressed)
+            // released = last & !now
+            .ld_r8_from_r8(GpRegister::A, GpRegister::B)
+            .cpl()
+            .and(GpRegister::C)
+            .ld_a_to_ind(joypad.released);
+
+        self.pop(StackPair::BC)
+            .pop(StackPair::AF)
+            .meta(Meta::end());
+
+        Ok(())
+    }
+
+    /// Tests whether any of `buttons` is in `edge`, returning the [Condition] that holds if so
+    /// 
+    /// Clobbers `a`, so this errors with [AssemblerError::RegisterInUse] while the allocator holds it: restoring `a`
+    /// would also restore the flags the result is read from. Use the result with [Assembler::jr], [Assembler::jp],
+    /// [Assembler::call] or [Assembler::ret]
+    fn test_buttons(&mut self, edge: ButtonEdge, buttons: Buttons) -> Result<Condition, Error> {
+        let joypad = self.allocator().borrow().state().joypad.ok_or(AssemblerError::JoypadUninitialized)?;
+        if self.reg_is_used(GpRegister::A) {
+            Err(AssemblerError::RegisterInUse(GpRegister::A))?
+        }
+
+        self.ld_a_from_ind(joypad.addr(edge))
+            .and_imm(buttons.bits());
+
+        Ok(CpuFlag::NZ.into())
+    }
+
+    /// Sets up sprites: allocates a cleared shadow OAM in WRAM and copies the OAM DMA routine into HRAM
+    /// 
+    /// Call this once at startup, before any [MacroAssembler::set_sprite]
+    fn init_oam_dma(&mut self) -> Result<(), Error> {
+        let shadow = self.alloc_var_aligned(OAM_SIZE, 0x100)?;
+        let routine: Vec<u8> = [
+            Instruction::<Meta>::LdhFromA(IoReg::Dma.into()),
+            // wait 160 cycles for the transfer to finish
+            Instruction::LdR8Imm(GpRegister::A, 40),
+            Instruction::DecR8(GpRegister::A),
+            Instruction::Jr(CpuFlag::NZ.into(), -3),
+            Instruction::Ret(Condition::Always),
+        ].into_iter().flat_map(Vec::<u8>::from).collect();
+        let src = self.new_stored_const(&routine)?;
+        let dest = self.alloc_hram(routine.len() as u16)?;
+        if src.bank != 0 {
+            self.require_rom0_code()?;
+        }
+
+        self.in_macro("init_oam_dma", |this| {
+            this.push(StackPair::AF)
+                .push(StackPair::BC)
+                .push(StackPair::HL);
+            this.push_rom_bank(src.bank)?;
+
+            // routine -> HRAM
+            this.ld_r16_imm(RegisterPair::HL, src.addr)
+                .ld_r8_imm(GpRegister::C, dest as u8)
+                .ld_r8_imm(GpRegister::B, routine.len() as u8)
+                .ld_a_from_r16(IndirectPair::HLInc)
+                .ldh_from_a_with_c()
+                .inc_r8(GpRegister::C)
+                .dec_r8(GpRegister::B)
+                .jr(CpuFlag::NZ, -6);
+
+            this.pop_rom_bank(src.bank);
+
+            // clear the shadow OAM, so unused sprites sit offscreen
+            this.ld_r16_imm(RegisterPair::HL, shadow)
+                .ld_r8_imm(GpRegister::B, OAM_SIZE as u8)
+                .ld_r8_imm(GpRegister::A, 0)
+                .ld_a_to_r16(IndirectPair::HLInc)
+                .dec_r8(GpRegister::B)
+                .jr(CpuFlag::NZ, -4);
+
+            this.pop(StackPair::HL)
+                .pop(StackPair::BC)
+                .pop(StackPair::AF);
+
+            Ok(())
+        })?;
+
+        let allocator = self.allocator();
+        let mut allocator = allocator.borrow_mut();
+        allocator.symbols_mut().rename(shadow.into(), "shadow_oam");
+        allocator.symbols_mut().rename(dest.into(), "oam_dma_routine");
+        allocator.state_mut().oam_dma = Some(OamDma { shadow, routine: dest });
+
+        Ok(())
+    }
+
+    /// Copies the shadow OAM into OAM through the HRAM DMA routine
+    /// 
+    /// Must run during VBlank, so this usually goes in the VBlank handler (see [Cgb::schedule_oam_dma](crate::Cgb::schedule_oam_dma)). Clobbers `a`
+    fn oam_dma(&mut self) -> Result<(), Error> {
+        let oam_dma = self.allocator().borrow().state().oam_dma.ok_or(AssemblerError::OamDmaUninitialized)?;
+
+        self.meta(Meta::macro_call("oam_dma"))
+            .ld_r8_imm(GpRegister::A, (oam_dma.shadow >> 8) as u8)
+            .call(Condition::Always, oam_dma.routine)
+            .meta(Meta::end());
+
+        Ok(())
+    }
+
+    /// Turns the APU on with every channel on both speakers at full master volume, and allocates the sound effect pointer
+    /// 
+    /// Call this once at startup, before any [MacroAssembler::play_sfx]
+    fn init_sound(&mut self) -> Result<(), Error> {
+        let pointer = self.alloc_var(2)?;
+
+        self.in_macro("init_sound", |this| {
+            this.set_ioreg(IoReg::Nr52, APU_ENABLE)?;
+            this.set_ioreg(IoReg::Nr51, 0xff)?;
+            this.set_ioreg(IoReg::Nr50, 0x77)?;
+            this.store_byte(pointer + 1, 0)
+        })?;
+
+        let allocator = self.allocator();
+        let mut allocator = allocator.borrow_mut();
+        allocator.symbols_mut().rename(pointer.into(), "sfx_pointer");
+        allocator.state_mut().sound = Some(Sound { pointer });
+
+        Ok(())
+    }
+
+    /// Fills wave RAM with 32 4-bit samples, high nibble first
+    /// 
+    /// Turns the wave channel's DAC off, since wave RAM can't be written reliably while it plays.
+    /// The next [SfxEvent::Wave](crate::apu::SfxEvent::Wave) turns it back on
+    fn load_wave_ram(&mut self, samples: &[u8; WAVE_RAM_SIZE as usize]) -> Result<(), Error> {
+        self.allocator().borrow().state().sound.ok_or(AssemblerError::SoundUninitialized)?;
+
+        self.in_macro("load_wave_ram", |this| {
+            this.set_ioreg(IoReg::Nr30, 0)?;
+            for (offset, &byte) in samples.iter().enumerate() {
+                this.store_byte(WAVE_RAM_BASE + offset as Addr, byte)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Starts playing `sfx` from its first frame on the next [MacroAssembler::step_sfx], cutting off whatever was playing
+    /// 
+    /// The effect is stored in ROM0, since the stepper runs from an interrupt and can't know which bank is mapped.
+    /// Errors if an event is out of range or ROM0 is out of space
+    fn play_sfx(&mut self, sfx: &SoundEffect) -> Result<(), Error> {
+        let sound = self.allocator().borrow().state().sound.ok_or(AssemblerError::SoundUninitialized)?;
+        if !sfx.is_valid() {
+            Err(Error::invalid_arg())?
+        }
+
+        let data = self.new_rom0_const(&sfx.to_bytes())?;
+
+        // the stepper could run between the two halves, so park it on a zero high byte while the low byte changes
+        self.in_macro("play_sfx", |this| {
+            this.store_byte(sound.pointer + 1, 0)?;
+            this.store_byte(sound.pointer, data.addr as u8)?;
+            this.store_byte(sound.pointer + 1, (data.addr >> 8) as u8)
+        })?;
+
+        Ok(())
+    }
+
+    /// Applies the next frame of the playing sound effect, if any
+    /// 
+    /// Call this once per frame, usually from the VBlank handler (see [Cgb::schedule_sfx](crate::Cgb::schedule_sfx))
+    fn step_sfx(&mut self) -> Result<(), Error> {
+        let sound = self.allocator().borrow().state().sound.ok_or(AssemblerError::SoundUninitialized)?;
+        let (lo, hi) = (sound.pointer, sound.pointer + 1);
+
+        self.meta(Meta::macro_call("step_sfx"))
+            .push(StackPair::AF)
+            .push(StackPair::BC)
+            .push(StackPair::HL);
+
+        // hl = pointer, skipping everything if nothing is playing
+        self.ld_a_from_ind(hi)
+            .or(GpRegister::A)
+            .jr(CpuFlag::Z, 35)
+            .ld_r8_from_r8(GpRegister::H, GpRegister::A)
+            .ld_a_from_ind(lo)
+            .ld_r8_from_r8(GpRegister::L, GpRegister::A);
+
+        // write count, or the end marker
+        self.ld_a_from_r16(IndirectPair::HLInc)
+            .cp_imm(SFX_END)
+            .jr(CpuFlag::Z, 21)
+            .or(GpRegister::A)
+            .jr(CpuFlag::Z, 8)
+            .ld_r8_from_r8(GpRegister::B, GpRegister::A);
+
+        // (register, value) pairs
+        self.ld_a_from_r16(IndirectPair::HLInc)
+            .ld_r8_from_r8(GpRegister::C, GpRegister::A)
+            .ld_a_from_r16(IndirectPair::HLInc)
+            .ldh_from_a_with_c()
+            .dec_r8(GpRegister::B)
+            .jr(CpuFlag::NZ, -7);
+
+        // save the pointer to the next frame
+        self.ld_r8_from_r8(GpRegister::A, GpRegister::L)
+            .ld_a_to_ind(lo)
+            .ld_r8_from_r8(GpRegister::A, GpRegister::H)
+            .ld_a_to_ind(hi)
+            .jr(Condition::Always, 4);
+
+        // finished, stop stepping
+        self.xor(GpRegister::A)
+            .ld_a_to_ind(hi);
+
+        self.pop(StackPair::HL)
+            .pop(StackPair::BC)
+            .pop(StackPair::AF)
+            .meta(Meta::end());
+
         Ok(())
     }
 
-    fn set_sprite(&mut self, sprite: Sprite, idx: SpriteIdx) {
-        let (_, _) = (sprite, idx);
-        todo!()
+    /// Allocates the palette fade pointer
+    /// 
+    /// Call this once at startup, before any [MacroAssembler::start_fade]. CGB only
+    fn init_fade(&mut self) -> Result<(), Error> {
+        self.require_cgb()?;
+        let pointer = self.alloc_var(2)?;
+
+        self.in_macro("init_fade", |this| this.store_byte(pointer + 1, 0))?;
+
+        let allocator = self.allocator();
+        let mut allocator = allocator.borrow_mut();
+        allocator.symbols_mut().rename(pointer.into(), "fade_pointer");
+        allocator.state_mut().fade = Some(Fade { pointer });
+
+        Ok(())
+    }
+
+    /// Starts `fade` from its first frame on the next [MacroAssembler::step_fade], cutting off whatever fade was running
+    /// 
+    /// The tables are stored in ROM0 like sound effects, at up to 160 bytes a frame when all 16 palettes change.
+    /// The tracked palettes are set to where the fade ends
+    fn start_fade(&mut self, fade: &PaletteFade) -> Result<(), Error> {
+        let state = self.allocator().borrow().state().fade.ok_or(AssemblerError::FadeUninitialized)?;
+
+        let data = self.new_rom0_const(&fade.to_bytes())?;
+
+        self.in_macro("start_fade", |this| {
+            this.store_byte(state.pointer + 1, 0)?;
+            this.store_byte(state.pointer, data.addr as u8)?;
+            this.store_byte(state.pointer + 1, (data.addr >> 8) as u8)
+        })?;
+
+        let allocator = self.allocator();
+        let mut allocator = allocator.borrow_mut();
+        let palettes = &mut allocator.state_mut().palettes;
+        palettes.background = fade.end.background;
+        palettes.object = fade.end.object;
+
+        Ok(())
+    }
+
+    /// Fades every written BG and OBJ palette from its current colors to `target` over `frames` frames, see [MacroAssembler::start_fade]
+    /// 
+    /// Errors if `frames` is 0
+    fn fade_palettes(&mut self, target: FadeTarget, frames: u8) -> Result<(), Error> {
+        if frames == 0 {
+            Err(Error::invalid_arg())?
+        }
+
+        let palettes = self.allocator().borrow().state().palettes;
+        self.start_fade(&PaletteFade::new(palettes, &target, frames))
+    }
+
+    /// Writes the next frame of the running fade to palette memory, if any
+    /// 
+    /// Palette memory is only accessible outside of drawing, so call this once per frame from the VBlank handler
+    /// (see [Cgb::schedule_fade](crate::Cgb::schedule_fade))
+    fn step_fade(&mut self) -> Result<(), Error> {
+        let fade = self.allocator().borrow().state().fade.ok_or(AssemblerError::FadeUninitialized)?;
+        let (lo, hi) = (fade.pointer, fade.pointer + 1);
+
+        self.meta(Meta::macro_call("step_fade"))
+            .push(StackPair::AF)
+            .push(StackPair::BC)
+            .push(StackPair::HL);
+
+        // hl = pointer, skipping everything if no fade is running
+        self.ld_a_from_ind(hi)
+            .or(GpRegister::A)
+            .jr(CpuFlag::Z, 52)
+            .ld_r8_from_r8(GpRegister::H, GpRegister::A)
+            .ld_a_from_ind(lo)
+            .ld_r8_from_r8(GpRegister::L, GpRegister::A);
+
+        // palette count, or the end marker
+        self.ld_a_from_r16(IndirectPair::HLInc)
+            .cp_imm(FADE_END)
+            .jr(CpuFlag::Z, 38)
+            .or(GpRegister::A)
+            .jr(CpuFlag::Z, 25)
+            .ld_r8_from_r8(GpRegister::B, GpRegister::A);
+
+        // spec register and selector, then 8 bytes through the data register right after it. The bytes are unrolled,
+        // a loop over them would push all 16 palettes past the end of VBlank
+        self.ld_a_from_r16(IndirectPair::HLInc)
+            .ld_r8_from_r8(GpRegister::C, GpRegister::A)
+            .ld_a_from_r16(IndirectPair::HLInc)
+            .ldh_from_a_with_c()
+            .inc_r8(GpRegister::C);
+        for _ in 0..8 {
+            self.ld_a_from_r16(IndirectPair::HLInc)
+                .ldh_from_a_with_c();
+        }
+        self.dec_r8(GpRegister::B)
+            .jr(CpuFlag::NZ, -24);
+
+        // save the pointer to the next frame
+        self.ld_r8_from_r8(GpRegister::A, GpRegister::L)
+            .ld_a_to_ind(lo)
+            .ld_r8_from_r8(GpRegister::A, GpRegister::H)
+            .ld_a_to_ind(hi)
+            .jr(Condition::Always, 4);
+
+        // finished, stop stepping
+        self.xor(GpRegister::A)
+            .ld_a_to_ind(hi);
+
+        self.pop(StackPair::HL)
+            .pop(StackPair::BC)
+            .pop(StackPair::AF)
+            .meta(Meta::end());
+
+        Ok(())
+    }
+
+    /// Waits for VBlank by polling LY, then disables the LCD, granting full access to PPU related memory
+    /// 
+    /// Doesn't need interrupts, but can spin for up to a full frame. LY stops moving while the LCD is off,
+    /// so the wait is skipped when it's known to be off, and at runtime when LCDC bit 7 is already clear
+    fn disable_lcd(&mut self) {
+        let known_off = self.allocator().borrow().state().lcd == LcdState::Off;
+        let reg_a = self.claim_reg(GpRegister::A, Id::Unset);
+
+        let block = self.basic_block();
+        block.meta(Meta::macro_call("disable_lcd"));
+        if !known_off {
+            block.ldh_to_a(IoReg::Lcdc.into())
+                .bit(reg_a.inner, Bit::_7)
+                .jr(CpuFlag::Z, 6)
+                .ldh_to_a(IoReg::Ly.into())
+                .cp_imm(144)
+                .jr(CpuFlag::C, -6);
+        }
+        block.ldh_to_a(IoReg::Lcdc.into())
+            .res(reg_a.inner, Bit::_7)
+            .ldh_from_a(IoReg::Lcdc.into())
+            .meta(Meta::end());
+
+        self.allocator().borrow_mut().state_mut().lcd = LcdState::Off;
     }
 
     /// Disables the LCD immediately, granting full access to PPU related memory
     /// 
     /// This should only be called during VBlank: [https://gbdev.io/pandocs/LCDC.html#lcdc7--lcd-enable]
-    fn disable_lcd_now(&mut self) {
+    /// 
+    /// Errors unless the LCD is known to be off or in VBlank, see [MacroAssembler::assume_vblank]. Use [MacroAssembler::disable_lcd] otherwise
+    fn disable_lcd_now(&mut self) -> Result<(), Error> {
+        self.check_lcd_disable()?;
+
         let reg_a = self.claim_reg(GpRegister::A, Id::Unset);
         
         self.basic_block()
+            .meta(Meta::macro_call("disable_lcd_now"))
             .ldh_to_a(IoReg::Lcdc.into())
             .res(reg_a.inner, Bit::_7)
-            .ldh_from_a(IoReg::Lcdc.into());
+            .ldh_from_a(IoReg::Lcdc.into())
+            .meta(Meta::end());
+
+        self.allocator().borrow_mut().state_mut().lcd = LcdState::Off;
+
+        Ok(())
+    }
+
+    /// Errors with [AssemblerError::UnsafeLcdDisable] unless the LCD is known to be off or in VBlank
+    fn check_lcd_disable(&self) -> Result<(), Error> {
+        let lcd = self.allocator().borrow().state().lcd;
+        if !lcd.can_disable() {
+            Err(AssemblerError::UnsafeLcdDisable(lcd))?
+        }
+
+        Ok(())
+    }
+
+    /// Tells the assembler that the code following this runs during VBlank, like in a VBlank interrupt handler
+    /// 
+    /// Emits nothing
+    fn assume_vblank(&mut self) {
+        self.allocator().borrow_mut().state_mut().lcd = LcdState::VBlank;
     }
 
     fn enable_lcd_now(&mut self) {
         let reg_a = self.claim_reg(GpRegister::A, Id::Unset);
         
         self.basic_block().open(|block| {
+            block.meta(Meta::macro_call("enable_lcd_now"));
             block.ldh_to_a(IoReg::Lcdc.into());
             block.set(reg_a.inner, Bit::_7);
             block.ldh_from_a(IoReg::Lcdc.into());
+            block.meta(Meta::end());
         });
+
+        self.allocator().borrow_mut().state_mut().lcd = LcdState::On;
+    }
+
+    /// Overwrites LCDC with `flags`
+    /// 
+    /// Turning the LCD off this way has the same restrictions as [MacroAssembler::disable_lcd_now]
+    fn set_lcdc(&mut self, flags: LcdcFlags) -> Result<(), Error> {
+        let enable = flags.contains(LcdcFlags::LCD_ENABLE);
+        if !enable {
+            self.check_lcd_disable()?;
+        }
+
+        self.set_ioreg(IoReg::Lcdc, flags.bits())?;
+        self.allocator().borrow_mut().state_mut().lcd = if enable { LcdState::On } else { LcdState::Off };
+
+        Ok(())
+    }
+
+    /// Sets the bits in `set` and clears the ones in `clear`, leaving the rest of LCDC as it is
+    /// 
+    /// Errors if a bit is in both. Clearing [LcdcFlags::LCD_ENABLE] has the same restrictions as [MacroAssembler::disable_lcd_now]
+    fn modify_lcdc(&mut self, set: LcdcFlags, clear: LcdcFlags) -> Result<(), Error> {
+        if clear.contains(LcdcFlags::LCD_ENABLE) {
+            self.check_lcd_disable()?;
+        }
+
+        self.modify_ioreg(IoReg::Lcdc, set.bits(), clear.bits())?;
+
+        if set.contains(LcdcFlags::LCD_ENABLE) {
+            self.allocator().borrow_mut().state_mut().lcd = LcdState::On;
+        } else if clear.contains(LcdcFlags::LCD_ENABLE) {
+            self.allocator().borrow_mut().state_mut().lcd = LcdState::Off;
+        }
+
+        Ok(())
+    }
+
+    /// Selects where BG and window tiles take their data from
+    fn set_bg_tiledata(&mut self, tiledata: TiledataSelector) -> Result<(), Error> {
+        let set = LcdcFlags::tiledata(tiledata);
+        self.modify_lcdc(set, LcdcFlags::TILEDATA.difference(set))
+    }
+
+    fn set_bg_tilemap(&mut self, tilemap: TilemapSelector) -> Result<(), Error> {
+        let set = LcdcFlags::bg_tilemap(tilemap);
+        self.modify_lcdc(set, LcdcFlags::BG_TILEMAP.difference(set))
+    }
+
+    fn set_window_tilemap(&mut self, tilemap: TilemapSelector) -> Result<(), Error> {
+        let set = LcdcFlags::window_tilemap(tilemap);
+        self.modify_lcdc(set, LcdcFlags::WINDOW_TILEMAP.difference(set))
+    }
+
+    fn set_obj_size(&mut self, size: ObjSize) -> Result<(), Error> {
+        let set = LcdcFlags::obj_size(size);
+        self.modify_lcdc(set, LcdcFlags::OBJ_SIZE.difference(set))?;
+
+        self.allocator().borrow_mut().state_mut().obj_size = size;
+        Ok(())
+    }
+
+    /// Selects which of [StatFlags::INTERRUPTS] request the STAT interrupt, disabling the others
+    /// 
+    /// The interrupt itself still has to be enabled in IE
+    fn set_stat_interrupts(&mut self, sources: StatFlags) -> Result<(), Error> {
+        self.set_ioreg(IoReg::Stat, sources.bits())
+    }
+
+    /// Enables the sources in `enable` and disables the ones in `disable`, leaving the others as they are
+    fn modify_stat_interrupts(&mut self, enable: StatFlags, disable: StatFlags) -> Result<(), Error> {
+        self.modify_ioreg(IoReg::Stat, enable.bits(), disable.bits())
+    }
+
+    /// Writes `value` to `reg`, which is either an [Constant::Inline8] or an 8-bit variable
+    /// 
+    /// Constants are validated like [MacroAssembler::store_byte], variables are only checked against read-only registers
+    fn set_ioreg_from(&mut self, reg: IoReg, value: &VarOrConst) -> Result<(), Error> {
+        let var = match value {
+            VarOrConst::Const(Constant::Inline8(value)) => return self.set_ioreg(reg, *value),
+            VarOrConst::Const(_) => Err(Error::invalid_arg())?,
+            VarOrConst::Var(var) => var,
+        };
+
+        if !var_is_r8(var) {
+            Err(Error::invalid_arg())?
+        }
+        self.check_ioreg_write(reg, 0)?;
+        let af_stacked = !var_in_a(var) && self.reg_is_used(GpRegister::A);
+
+        self.in_macro("set_ioreg_from", |this| {
+            if af_stacked { this.push(StackPair::AF); }
+            this.ld_a_from_var(var)?
+                .ldh_from_a(reg.into());
+            if af_stacked { this.pop(StackPair::AF); }
+
+            Ok(())
+        })
+    }
+
+    fn set_scx(&mut self, value: &VarOrConst) -> Result<(), Error> {
+        self.set_ioreg_from(IoReg::Scx, value)
+    }
+
+    fn set_scy(&mut self, value: &VarOrConst) -> Result<(), Error> {
+        self.set_ioreg_from(IoReg::Scy, value)
+    }
+
+    /// Sets the window's left edge plus [WINDOW_X_OFFSET](crate::ppu::lcd::WINDOW_X_OFFSET), so 7 lines it up with the left of the screen
+    fn set_wx(&mut self, value: &VarOrConst) -> Result<(), Error> {
+        self.set_ioreg_from(IoReg::Wx, value)
+    }
+
+    /// Sets the window's top edge, anything past 143 hides it
+    fn set_wy(&mut self, value: &VarOrConst) -> Result<(), Error> {
+        self.set_ioreg_from(IoReg::Wy, value)
+    }
+
+    /// Shows the window, drawn from `tilemap` at the position in WX and WY
+    /// 
+    /// On DMG, [LcdcFlags::BG_ENABLE] hides the window along with the background
+    fn enable_window(&mut self, tilemap: TilemapSelector) -> Result<(), Error> {
+        let set = LcdcFlags::WINDOW_ENABLE | LcdcFlags::window_tilemap(tilemap);
+        self.modify_lcdc(set, LcdcFlags::WINDOW_TILEMAP.difference(set))
+    }
+
+    fn disable_window(&mut self) -> Result<(), Error> {
+        self.modify_lcdc(LcdcFlags::empty(), LcdcFlags::WINDOW_ENABLE)
     }
 
-    fn store_byte(&mut self, addr: Addr, value: u8) {
+    /// Adds the 8-bit variable `delta` to `reg`, wrapping around, so a two's complement `delta` moves either way
+    /// 
+    /// Preserves every register
+    fn add_to_ioreg(&mut self, reg: IoReg, delta: &Variable) -> Result<(), Error> {
+        if !var_is_r8(delta) {
+            Err(Error::invalid_arg())?
+        }
+        self.check_ioreg_write(reg, 0)?;
+
+        self.in_macro("add_to_ioreg", |this| {
+            this.push(StackPair::AF)
+                .push(StackPair::HL)
+                .ld_a_from_var(delta)?
+                .ld_r16_imm(RegisterPair::HL, reg.addr())
+                .add(GpRegister::IndHL)
+                .ld_r8_from_r8(GpRegister::IndHL, GpRegister::A)
+                .pop(StackPair::HL)
+                .pop(StackPair::AF);
+
+            Ok(())
+        })
+    }
+
+    /// Scrolls the background by the signed deltas in `dx` and `dy`, see [Cgb::schedule_scroll](crate::Cgb::schedule_scroll)
+    /// to do it every frame
+    fn scroll_by(&mut self, dx: &Variable, dy: &Variable) -> Result<(), Error> {
+        self.add_to_ioreg(IoReg::Scx, dx)?;
+        self.add_to_ioreg(IoReg::Scy, dy)
+    }
+
+    /// Sets the bits in `set` and clears the ones in `clear` with a read-modify-write, preserving every register
+    /// 
+    /// Errors if a bit is in both, and validates the bits written like [MacroAssembler::store_byte]
+    fn modify_ioreg(&mut self, reg: IoReg, set: u8, clear: u8) -> Result<(), Error> {
+        if set & clear != 0 {
+            Err(Error::invalid_arg())?
+        }
+        self.check_ioreg_write(reg, set | clear)?;
+
+        self.meta(Meta::macro_call("modify_ioreg"))
+            .push(StackPair::AF)
+            .ldh_to_a(reg.into());
+        if clear != 0 {
+            self.and_imm(!clear);
+        }
+        if set != 0 {
+            self.or_imm(set);
+        }
+        self.ldh_from_a(reg.into())
+            .pop(StackPair::AF)
+            .meta(Meta::end());
+
+        Ok(())
+    }
+
+    /// Writes `value` to `addr`, preserving every register
+    /// 
+    /// Writes to hardware registers are checked against [IoReg::info] first: read-only registers, bits the register
+    /// ignores and registers the target model doesn't have are all refused. Writes to VBK update the tracked VRAM bank
+    fn store_byte(&mut self, addr: Addr, value: u8) -> Result<(), Error> {
+        if let Ok(reg) = IoReg::try_from(addr) {
+            self.check_ioreg_write(reg, value)?;
+        }
+
+        self.meta(Meta::macro_call("store_byte"));
         let reg = self.alloc_reg();
 
         if let Ok(reg) = reg {
@@ -384,10 +1482,313 @@ pub trait MacroAssembler<Meta, Error, AllocError>: Assembler<Meta> + Variabler<M
 
             self.pop(StackPair::AF);
         }
+
+        self.meta(Meta::end());
+
+        if addr == IoReg::Vbk.addr() {
+            let bank = if value & 1 == 0 { VramBank::_0 } else { VramBank::_1 };
+            self.allocator().borrow_mut().state_mut().vram_bank = bank;
+        }
+
+        Ok(())
+    }
+
+    fn set_ioreg(&mut self, ioreg: IoReg, value: u8) -> Result<(), Error> {
+        self.store_byte(ioreg.addr(), value)
+    }
+
+    /// Errors if writing `value` to `reg` would be refused by [MacroAssembler::store_byte]
+    fn check_ioreg_write(&self, reg: IoReg, value: u8) -> Result<(), Error> {
+        let model = self.allocator().borrow().state().model;
+        let info = reg.info();
+
+        if !reg.exists_on(model) {
+            Err(AssemblerError::UnavailableRegister(reg, model))?
+        }
+        if info.writable == 0 {
+            Err(AssemblerError::ReadOnlyRegister(reg))?
+        }
+        if value & !info.writable != 0 {
+            Err(AssemblerError::UnwritableBits { reg, value })?
+        }
+
+        Ok(())
+    }
+
+    /// Sets IE to `interrupts`, disabling every other interrupt source
+    /// 
+    /// Interrupts still have to be enabled with [Assembler::ei] to be serviced
+    fn set_interrupt_enable(&mut self, interrupts: Interrupts) -> Result<(), Error> {
+        self.set_ioreg(IoReg::Ie, interrupts.bits())
+    }
+
+    /// Enables `interrupts` in IE, leaving the others as they are
+    fn enable_interrupts(&mut self, interrupts: Interrupts) {
+        self.meta(Meta::macro_call("enable_interrupts"))
+            .push(StackPair::AF)
+            .ldh_to_a(IoReg::Ie.into())
+            .or_imm(interrupts.bits())
+            .ldh_from_a(IoReg::Ie.into())
+            .pop(StackPair::AF)
+            .meta(Meta::end());
+    }
+
+    /// Acknowledges pending `interrupts` by clearing their bits in IF, leaving the others pending
+    fn clear_pending_interrupts(&mut self, interrupts: Interrupts) {
+        self.meta(Meta::macro_call("clear_pending_interrupts"))
+            .push(StackPair::AF)
+            .ldh_to_a(IoReg::If.into())
+            .and_imm(!interrupts.bits())
+            .ldh_from_a(IoReg::If.into())
+            .pop(StackPair::AF)
+            .meta(Meta::end());
+    }
+
+    /// Acknowledges every pending interrupt by clearing IF
+    /// 
+    /// Useful right before [Assembler::ei], since IF can have stale bits set from before they were enabled
+    fn clear_interrupt_flags(&mut self) -> Result<(), Error> {
+        self.set_ioreg(IoReg::If, 0x00)
     }
 
-    fn set_ioreg(&mut self, ioreg: IoReg, value: u8) {
-        self.store_byte(ioreg as u16, value);
+    /// Resets DIV to 0, which also restarts the timer's current tick
+    fn reset_divider(&mut self) -> Result<(), Error> {
+        self.set_ioreg(IoReg::Div, 0x00)
+    }
+
+    /// `ldh a, [DIV]`
+    fn read_divider(&mut self) -> &mut Self {
+        self.ldh_to_a(IoReg::Div.into())
+    }
+
+    /// `ldh a, [TIMA]`
+    fn read_timer_counter(&mut self) -> &mut Self {
+        self.ldh_to_a(IoReg::Tima.into())
+    }
+
+    fn set_timer_counter(&mut self, value: u8) -> Result<(), Error> {
+        self.set_ioreg(IoReg::Tima, value)
+    }
+
+    fn set_timer_modulo(&mut self, modulo: u8) -> Result<(), Error> {
+        self.set_ioreg(IoReg::Tma, modulo)
+    }
+
+    fn set_timer_control(&mut self, control: TimerControl) -> Result<(), Error> {
+        self.set_ioreg(IoReg::Tac, control.into())
+    }
+
+    /// Stops the timer, loads `config` and starts it again from a full period
+    fn start_timer(&mut self, config: TimerConfig) -> Result<(), Error> {
+        self.in_macro("start_timer", |this| {
+            this.set_timer_control(TimerControl { clock: config.clock, enable: false })?;
+            this.set_timer_modulo(config.modulo)?;
+            this.set_timer_counter(config.modulo)?;
+            this.set_timer_control(TimerControl { clock: config.clock, enable: true })
+        })?;
+
+        self.allocator().borrow_mut().state_mut().timer = Some(config);
+        Ok(())
+    }
+
+    /// Starts the timer overflowing as close to `hz` times a second as it can, and enables the Timer interrupt in IE
+    /// 
+    /// The handler goes in [Cgb::interrupt](crate::Cgb::interrupt), and interrupts still have to be enabled with [Assembler::ei].
+    /// Accounts for the current [CpuSpeed], so this has to be called again after [MacroAssembler::set_speed] to keep the rate.
+    /// Returns the config used, see [TimerConfig::for_frequency_at] for the range of `hz`
+    fn set_timer_frequency(&mut self, hz: u32) -> Result<TimerConfig, Error> {
+        let speed = self.allocator().borrow().state().speed;
+        let config = TimerConfig::for_frequency_at(hz, speed).ok_or_else(Error::invalid_arg)?;
+
+        self.start_timer(config)?;
+        self.clear_pending_interrupts(Interrupts::TIMER);
+        self.enable_interrupts(Interrupts::TIMER);
+
+        Ok(config)
+    }
+
+    /// Switches the CPU to `speed` through KEY1 and `stop`, doing nothing at runtime if it's already running at `speed`
+    /// 
+    /// IE is cleared and P1 deselected around the `stop`, so neither an interrupt nor a held button can wake the CPU
+    /// before the switch is done. IE is restored afterwards. Timers configured before the switch change rate with it.
+    /// CGB only
+    fn set_speed(&mut self, speed: CpuSpeed) -> Result<(), Error> {
+        self.require_cgb()?;
+        let ie: u8 = IoReg::Ie.into();
+        let key1: u8 = IoReg::Key1.into();
+        let already = match speed {
+            CpuSpeed::Normal => CpuFlag::Z,
+            CpuSpeed::Double => CpuFlag::NZ,
+        };
+
+        self.meta(Meta::macro_call("set_speed"))
+            .push(StackPair::AF)
+            .ldh_to_a(key1)
+            .bit(GpRegister::A, Bit::_7)
+            .jr(already, 19)
+            .ldh_to_a(ie)
+            .push(StackPair::AF)
+            .xor(GpRegister::A)
+            .ldh_from_a(ie)
+            .ld_r8_imm(GpRegister::A, SELECT_NONE)
+            .ldh_from_a(IoReg::P1.into())
+            .ld_r8_imm(GpRegister::A, 0x01)
+            .ldh_from_a(key1)
+            .stop()
+            .pop(StackPair::AF)
+            .ldh_from_a(ie)
+            .pop(StackPair::AF)
+            .meta(Meta::end());
+
+        self.allocator().borrow_mut().state_mut().speed = speed;
+
+        Ok(())
+    }
+
+    /// Maps ROM bank `bank` into the ROMX window, keeping [ROM_BANK_SHADOW] up to date
+    /// 
+    /// Clobbers `a`. Errors with [AssemblerError::RomBankFromRomx] in code that is itself running in ROMX
+    fn switch_rom_bank(&mut self, bank: u16) -> Result<(), Error> {
+        self.require_rom0_code()?;
+        let bank: u8 = bank.try_into().map_err(|_| Error::invalid_arg())?;
+        self.meta(Meta::macro_call("switch_rom_bank"));
+
+        self.ld_r8_imm(GpRegister::A, bank)
+            .ldh_from_a(ROM_BANK_SHADOW as u8)
+            .ld_a_to_ind(ROM_BANK_SELECT);
+
+        self.meta(Meta::end());
+        Ok(())
+    }
+
+    /// Saves the current ROMX bank on the stack and switches to `bank`
+    /// 
+    /// Does nothing for ROM0 (`bank == 0`), so it can wrap reads from any [StoredConstant]
+    /// 
+    /// Must be paired with [MacroAssembler::pop_rom_bank] using the same `bank`. Clobbers `a` and the flags until then,
+    /// the pop restores both. Errors with [AssemblerError::RomBankFromRomx] in code that is itself running in ROMX
+    fn push_rom_bank(&mut self, bank: u16) -> Result<(), Error> {
+        if bank == 0 {
+            return Ok(());
+        }
+        self.require_rom0_code()?;
+        let bank: u8 = bank.try_into().map_err(|_| Error::invalid_arg())?;
+
+        self.meta(Meta::macro_call("push_rom_bank"))
+            .push(StackPair::AF)
+            .ldh_to_a(ROM_BANK_SHADOW as u8)
+            .push(StackPair::AF)
+            .ld_r8_imm(GpRegister::A, bank)
+            .ldh_from_a(ROM_BANK_SHADOW as u8)
+            .ld_a_to_ind(ROM_BANK_SELECT)
+            .meta(Meta::end());
+
+        Ok(())
+    }
+
+    /// Errors with [AssemblerError::RomBankFromRomx] when the code being built lives in a ROMX bank
+    fn require_rom0_code(&self) -> Result<(), Error> {
+        match self.rom_bank() {
+            0 => Ok(()),
+            bank => Err(AssemblerError::RomBankFromRomx(bank).into()),
+        }
+    }
+
+    /// Restores the ROMX bank saved by [MacroAssembler::push_rom_bank]
+    fn pop_rom_bank(&mut self, bank: u16) {
+        if bank == 0 {
+            return;
+        }
+
+        self.meta(Meta::macro_call("pop_rom_bank"))
+            .pop(StackPair::AF)
+            .ldh_from_a(ROM_BANK_SHADOW as u8)
+            .ld_a_to_ind(ROM_BANK_SELECT)
+            .pop(StackPair::AF)
+            .meta(Meta::end());
+    }
+
+    /// Enables cartridge RAM and maps in bank `bank`
+    /// 
+    /// Prefer [MacroAssembler::with_sram], which disables it again afterwards
+    fn enable_sram(&mut self, bank: u16) -> Result<(), Error> {
+        let mbc = self.allocator().borrow().mbc();
+        let bank: u8 = bank.try_into().map_err(|_| Error::invalid_arg())?;
+
+        if mbc.max_ram_banks() == 0 {
+            Err(ConstAllocError::NoCartridgeRam)?
+        }
+
+        self.in_macro("enable_sram", |this| {
+            this.store_byte(RAM_ENABLE, RAM_ENABLE_VALUE)?;
+
+            if mbc == Mbc::Mbc1 {
+                // RAM banking mode, otherwise MBC1 ignores the RAM bank
+                this.store_byte(MBC1_MODE_SELECT, 1)?;
+            }
+
+            this.store_byte(RAM_BANK_SELECT, bank)
+        })
+    }
+
+    /// Disables cartridge RAM, protecting it from stray writes (and power loss)
+    fn disable_sram(&mut self) -> Result<(), Error> {
+        self.store_byte(RAM_ENABLE, 0x00)
+    }
+
+    /// Runs `inner` with `var`'s cartridge RAM bank enabled, then disables cartridge RAM again
+    /// 
+    /// `inner` reads and writes the variable through an [SramGuard]. Cartridge RAM is disabled again even if `inner` fails
+    fn with_sram<F>(&mut self, var: &SramVariable, inner: F) -> Result<(), Error>
+            where F: FnOnce(&mut SramGuard<'_, Meta>) -> Result<(), AssemblerError> {
+        self.enable_sram(var.bank)?;
+
+        let out = inner(&mut SramGuard::new(self.basic_block(), var));
+        self.disable_sram()?;
+        out?;
+
+        Ok(())
+    }
+
+    /// Calls `addr` in ROM bank `bank` through the [FAR_CALL] trampoline, restoring the current bank afterwards
+    /// 
+    /// `hl` and `a` are saved around the call when the allocator holds them, the flags are not preserved
+    fn far_call(&mut self, bank: u16, addr: Addr) -> Result<(), Error> {
+        let bank: u8 = bank.try_into().map_err(|_| Error::invalid_arg())?;
+        self.meta(Meta::macro_call("far_call"));
+
+        let hl_stacked = self.reg_is_used(RegisterPair::HL);
+        let af_stacked = self.reg_is_used(GpRegister::A);
+        if hl_stacked { self.push(StackPair::HL); }
+        if af_stacked { self.push(StackPair::AF); }
+
+        self.ld_r16_imm(RegisterPair::HL, addr)
+            .ld_r8_imm(GpRegister::A, bank)
+            .call(Condition::Always, FAR_CALL);
+
+        if af_stacked { self.pop(StackPair::AF); }
+        if hl_stacked { self.pop(StackPair::HL); }
+
+        self.meta(Meta::end());
+        Ok(())
+    }
+
+    /// Jumps to `addr` in ROM bank `bank` through the [FAR_JP] trampoline
+    /// 
+    /// Nothing comes back from the jump, so this errors with [AssemblerError::FarJumpClobbers] while the allocator holds `hl` or `a`
+    fn far_jp(&mut self, bank: u16, addr: Addr) -> Result<(), Error> {
+        let bank: u8 = bank.try_into().map_err(|_| Error::invalid_arg())?;
+        if self.reg_is_used(RegisterPair::HL) || self.reg_is_used(GpRegister::A) {
+            Err(AssemblerError::FarJumpClobbers)?
+        }
+
+        self.meta(Meta::macro_call("far_jp"))
+            .ld_r16_imm(RegisterPair::HL, addr)
+            .ld_r8_imm(GpRegister::A, bank)
+            .jp(Condition::Always, FAR_JP)
+            .meta(Meta::end());
+
+        Ok(())
     }
     
     fn init_var8<T>(&mut self, value: T) -> Result<Variable, Error>
@@ -442,6 +1843,30 @@ impl AsBuf for u16 {
     }
 }
 
+/// Whether `src`, `dest` and `len` can be handed to [MacroAssembler::general_dma] or [MacroAssembler::hblank_dma]
+fn vram_dma_args_valid(src: Addr, dest: Addr, len: u16) -> bool {
+    let src_ok = src.is_multiple_of(VRAM_DMA_BLOCK) && !(VRAM_BASE..VRAM_BASE + VRAM_SIZE).contains(&src) && src < 0xe000;
+    let dest_ok = dest.is_multiple_of(VRAM_DMA_BLOCK) && dest >= VRAM_BASE && dest as u32 + len as u32 <= (VRAM_BASE + VRAM_SIZE) as u32;
+    let len_ok = len.is_multiple_of(VRAM_DMA_BLOCK) && (VRAM_DMA_BLOCK..=VRAM_DMA_MAX_LEN).contains(&len);
+
+    src_ok && dest_ok && len_ok
+}
+
+/// Whether `var` lives in `a`, which loading anything else into `a` would clobber
+fn var_in_a(var: &Variable) -> bool {
+    matches!(var, Variable::Reg(reg) if matches!(reg.inner(),
+        RawRegVariable::R8 { reg: GpRegister::A, .. } | RawRegVariable::MemR8 { reg: GpRegister::A, .. }))
+}
+
+/// Whether `var` is something [Variabler::ld_a_from_var] can load: a 1-byte memory variable or an 8-bit register
+fn var_is_r8(var: &Variable) -> bool {
+    match var {
+        Variable::Memory(MemoryVariable { len: 1, .. }) => true,
+        Variable::Reg(reg) => matches!(reg.inner(), RawRegVariable::R8 { .. } | RawRegVariable::MemR8 { .. }),
+        _ => false,
+    }
+}
+
 pub trait ErrorTrait {
     fn invalid_arg() -> Self where Self: Sized;
 }
\ No newline at end of file
@@ -2,7 +2,7 @@ use basic_block::BasicBlock;
 use loop_block::LoopBlock;
 use raw_block::RawBlock;
 
-use crate::cpu::instructions::Instruction;
+use crate::{cpu::instructions::Instruction, memory::Addr};
 
 use super::{meta_instr::MetaInstructionTrait, variables::Constant, Assembler, AssemblerError, MacroAssembler, Variable};
 
@@ -29,6 +29,45 @@ impl<Meta> Block<Meta>
     }
 }
 
+impl<Meta> Block<Meta>
+        where Meta: Clone + std::fmt::Debug + MetaInstructionTrait, {
+    /// Walks the block as it would be laid out starting at `addr`, returning the address right after it
+    /// 
+    /// `path` names the enclosing blocks, and is extended for [BasicBlock]s and [LoopBlock]s (but not raw instruction runs)
+    pub fn walk<V>(&self, path: &mut Vec<String>, addr: Addr, visitor: &mut V) -> Result<Addr, Vec<AssemblerError>>
+            where V: BlockVisitor<Meta> {
+        match self {
+            Self::Basic(block) => block.walk(path, addr, visitor),
+            Self::Loop(block) => {
+                visitor.enter(path, addr);
+                let addr = block.inner.walk_contents(path, addr, visitor)?;
+                let footer = block.footer()?.flatten().map_err(|err| vec![err])?;
+                Block::Raw(footer).walk(path, addr, visitor)
+            }
+            Self::Raw(block) => {
+                Ok(block.0.iter().fold(addr, |addr, instruction| {
+                    visitor.instruction(path, addr, instruction);
+                    addr + instruction.len() as Addr
+                }))
+            }
+        }
+    }
+}
+
+/// Callbacks for [Block::walk]
+pub trait BlockVisitor<Meta>
+        where Meta: Clone + std::fmt::Debug + MetaInstructionTrait, {
+    /// Called when entering a [BasicBlock] or [LoopBlock] placed at `addr`
+    fn enter(&mut self, path: &[String], addr: Addr) {
+        let _ = (path, addr);
+    }
+
+    /// Called for every instruction, including ones that aren't emitted (like labels)
+    fn instruction(&mut self, path: &[String], addr: Addr, instruction: &Instruction<Meta>) {
+        let _ = (path, addr, instruction);
+    }
+}
+
 impl<Meta> Default for Block<Meta>
         where Meta: Clone + std::fmt::Debug + MetaInstructionTrait, {
     fn default() -> Self {
@@ -114,6 +153,10 @@ impl<Meta> Assembler<Meta> for Block<Meta>
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum EmitterError {
     UnallocatedVariable(Variable),
+    /// [BasicBlock::flatten] met a [LoopBlock], which needs its footer laid out
+    LoopNotFlattenable,
+    /// A loop's body and footer add up to this many bytes, too far back for `jr` to reach
+    LoopTooBig(usize),
 }
 
 pub trait BlockTrait {
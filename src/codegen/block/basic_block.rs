@@ -9,9 +9,12 @@ use crate::codegen::variables::{Constant, StoredConstant, Variabler};
 use crate::codegen::{Assembler, AssemblerError, Id, LoopCondition, MacroAssembler};
 use crate::codegen::{Block, LoopBlock};
 use crate::codegen::{IdInner, Variable};
+use crate::cartridge::BankedAddr;
 use crate::cpu::instructions::Instruction;
 
-use super::BlockTrait;
+use crate::memory::Addr;
+
+use super::{raw_block::RawBlock, BlockTrait, BlockVisitor, EmitterError};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BasicBlock<Meta>
@@ -19,6 +22,8 @@ pub struct BasicBlock<Meta>
     next_id: IdInner,
     pub contents: Vec<Block<Meta>>,
     pub allocator: Rc<RefCell<ConstAllocator>>,
+    /// ROM bank the block's code ends up in, 0 unless it's part of a [BankedSection](crate::codegen::cgb::BankedSection)
+    pub rom_bank: u16,
     // pub variables: HashMap<Id, Variable>,
     pub consts: HashMap<Id, (StoredConstant, Vec<u8>)>,
 }
@@ -43,10 +48,67 @@ impl<Meta> BasicBlock<Meta>
             next_id: Default::default(),
             contents: Vec::with_capacity(4),
             allocator,
+            rom_bank: 0,
             // variables: Default::default(),
             consts: Default::default(),
         }
     }
+
+    /// See [Block::walk]
+    pub fn walk<V>(&self, path: &mut Vec<String>, addr: Addr, visitor: &mut V) -> Result<Addr, Vec<AssemblerError>>
+            where V: BlockVisitor<Meta> {
+        visitor.enter(path, addr);
+        self.walk_contents(path, addr, visitor)
+    }
+
+    /// Walks this block's contents without entering the block itself, see [Block::walk]
+    pub fn walk_contents<V>(&self, path: &mut Vec<String>, addr: Addr, visitor: &mut V) -> Result<Addr, Vec<AssemblerError>>
+            where V: BlockVisitor<Meta> {
+        self.contents.iter().enumerate().try_fold(addr, |addr, (idx, block)| {
+            let name = match block {
+                Block::Basic(_) => format!("basic_block#{idx}"),
+                Block::Loop(_) => format!("loop#{idx}"),
+                Block::Raw(_) => return block.walk(path, addr, visitor),
+            };
+
+            path.push(name);
+            let out = block.walk(path, addr, visitor);
+            path.pop();
+
+            out
+        })
+    }
+
+    /// Collects every instruction in emission order, for blocks that don't need any layout (like loop footers)
+    /// 
+    /// Errors with [EmitterError::LoopNotFlattenable] if the block contains a loop
+    pub fn flatten(&self) -> Result<RawBlock<Meta>, AssemblerError> {
+        let mut out = RawBlock::default();
+        for block in self.contents.iter() {
+            match block {
+                Block::Basic(block) => out.0.extend(block.flatten()?.0),
+                Block::Raw(block) => out.0.extend(block.0.iter().cloned()),
+                Block::Loop(_) => Err(EmitterError::LoopNotFlattenable)?,
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Records `data` to be written at `addr`, which the allocator has already handed out
+    fn insert_const(&mut self, addr: BankedAddr, data: &[u8]) -> StoredConstant {
+        let id = self.new_id();
+        let constant = StoredConstant {
+            id,
+            bank: addr.bank,
+            addr: addr.addr,
+            len: data.len() as u16
+        };
+
+        self.consts.insert(id, (constant, data.to_vec()));
+
+        constant
+    }
 }
 
 // impl<Meta> From<Vec<Instruction<Meta>>> for BasicBlock<Meta>
@@ -126,7 +188,8 @@ impl<Meta> Variabler<Meta, AssemblerError, ConstAllocError> for BasicBlock<Meta>
 impl<Meta> BlockAssembler<Meta> for BasicBlock<Meta>
         where Meta: Clone + std::fmt::Debug + MetaInstructionTrait, {
     fn basic_block(&mut self) -> &mut BasicBlock<Meta> {
-        let block: BasicBlock<Meta> = BasicBlock::new(self.allocator.clone());
+        let mut block: BasicBlock<Meta> = BasicBlock::new(self.allocator.clone());
+        block.rom_bank = self.rom_bank;
         self.contents.push(Block::Basic(block));
 
         if let Block::Basic(ref mut last) = self.contents.last_mut().unwrap() {
@@ -137,7 +200,9 @@ impl<Meta> BlockAssembler<Meta> for BasicBlock<Meta>
     }
 
     fn loop_block(&mut self, condition: LoopCondition) -> &mut LoopBlock<Meta> {
-        let block: LoopBlock<Meta> = LoopBlock::<Meta>::new(condition, BasicBlock::new(self.allocator.clone()));
+        let mut inner = BasicBlock::new(self.allocator.clone());
+        inner.rom_bank = self.rom_bank;
+        let block: LoopBlock<Meta> = LoopBlock::<Meta>::new(condition, inner);
         self.contents.push(block.into());
 
         if let Block::Loop(ref mut last) = self.contents.last_mut().unwrap() {
@@ -146,22 +211,26 @@ impl<Meta> BlockAssembler<Meta> for BasicBlock<Meta>
             unreachable!()
         }
     }
+
+    fn rom_bank(&self) -> u16 {
+        self.rom_bank
+    }
 }
 
 impl<Meta> MacroAssembler<Meta, AssemblerError, ConstAllocError> for BasicBlock<Meta>
         where Meta: Clone + std::fmt::Debug + MetaInstructionTrait, {
     fn new_stored_const(&mut self, data: &[u8]) -> Result<StoredConstant, AssemblerError> {
-        let addr = self.allocator.borrow_mut().alloc_const(data.len() as u16)?;
-        let id = self.new_id();
-        let constant = StoredConstant {
-            id,
-            addr,
-            len: data.len() as u16
-        };
+        self.new_stored_const_aligned(data, 1)
+    }
 
-        self.consts.insert(id, (constant, data.to_vec()));
+    fn new_stored_const_aligned(&mut self, data: &[u8], align: u16) -> Result<StoredConstant, AssemblerError> {
+        let addr = self.allocator.borrow_mut().alloc_const_aligned(data.len() as u16, align)?;
+        Ok(self.insert_const(addr, data))
+    }
 
-        Ok(constant)
+    fn new_rom0_const(&mut self, data: &[u8]) -> Result<StoredConstant, AssemblerError> {
+        let addr = self.allocator.borrow_mut().alloc_rom0_const(data.len() as u16)?;
+        Ok(self.insert_const(addr, data))
     }
 
     fn new_inline_const_r8(&mut self, data: u8) -> Constant {
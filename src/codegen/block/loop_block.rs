@@ -18,10 +18,11 @@ use crate::{
             Instruction
         },
         CpuFlag
-    }
+    },
+    memory::Addr,
 };
 
-use super::{basic_block::BasicBlock, Block, BlockTrait};
+use super::{basic_block::BasicBlock, Block, BlockTrait, EmitterError};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum LoopCondition {
@@ -31,6 +32,10 @@ pub enum LoopCondition {
     Countdown { counter: RawVariable, end: u16 },
     /// Increments `counter` until it reaches `end`, then stops iterating
     Countup { counter: RawVariable, end: u16 },
+    /// Tests `[addr] & mask` after every iteration, looping while it's nonzero (`while_set`) or zero (`!while_set`)
+    /// 
+    /// Clobbers `a`
+    Bits { addr: Addr, mask: u8, while_set: bool },
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -59,26 +64,27 @@ impl<Meta> LoopBlock<Meta>
     }
 }
 
-impl<Meta> TryFrom<LoopBlock<Meta>> for Vec<u8>
+impl<Meta> LoopBlock<Meta>
         where Meta: Clone + std::fmt::Debug + MetaInstructionTrait, {
-    type Error = Vec<AssemblerError>;
-
-    fn try_from(value: LoopBlock<Meta>) -> Result<Self, Self::Error> {
-        let mut errs: Self::Error = Vec::new();
+    /// Instructions emitted after the body, jumping back to its start while the condition holds
+    pub fn footer(&self) -> Result<BasicBlock<Meta>, Vec<AssemblerError>> {
+        let mut errs: Vec<AssemblerError> = Vec::new();
 
         // when jumping backwards the offset must include the Jr itself (2 bytes)
-        let block_length = value.inner.len();
+        let block_length = self.inner.len();
 
         // Jr takes a signed 8-bit integer
-        if block_length as isize * -1 < i8::MIN as isize {
-            todo!("Loop body too big")
-        }
+        let jump_back = |loop_length: usize| i8::try_from(-(loop_length as isize))
+            .map_err(|_| AssemblerError::from(EmitterError::LoopTooBig(loop_length)));
 
-        let allocator = value.allocator();
-        let jump: Result<Vec<u8>, Self::Error> = match value.condition {
+        let allocator = self.allocator();
+        let buffer = match self.condition {
             LoopCondition::Native(condition) => {
                 let mut buffer = BasicBlock::<Meta>::new(allocator);
-                buffer.jr(condition, block_length as i8 * -1);
+                match jump_back(block_length) {
+                    Ok(offset) => { buffer.jr(condition, offset); },
+                    Err(err) => errs.push(err),
+                }
                 buffer
             },
             LoopCondition::Countdown { ref counter, end }
@@ -94,19 +100,45 @@ impl<Meta> TryFrom<LoopBlock<Meta>> for Vec<u8>
                     // especially when all registers are in use
 
                     let loop_length = block_length + buffer.len() + LoopBlock::<Meta>::JR_LEN;
-                    
-                    if loop_length as isize * -1 < i8::MIN as isize {
-                        todo!("Loop body too big")
+                    match jump_back(loop_length) {
+                        Ok(offset) => { buffer.jr(Condition::Flag(CpuFlag::NZ), offset); },
+                        Err(err) => errs.push(err),
                     }
-
-                    buffer.jr(Condition::Flag(CpuFlag::NZ), loop_length as i8 * -1);
                     buffer
                 } else {
                     todo!()
                 }
             },
-        }.try_into();
+            LoopCondition::Bits { addr, mask, while_set } => {
+                let mut buffer = BasicBlock::<Meta>::new(allocator);
+                buffer.ld_a_from_ind(addr).and_imm(mask);
+
+                let loop_length = block_length + buffer.len() + LoopBlock::<Meta>::JR_LEN;
+                let condition = if while_set { CpuFlag::NZ } else { CpuFlag::Z };
+                match jump_back(loop_length) {
+                    Ok(offset) => { buffer.jr(condition, offset); },
+                    Err(err) => errs.push(err),
+                }
+                buffer
+            },
+        };
+
+        if !errs.is_empty() {
+            Err(errs)
+        } else {
+            Ok(buffer)
+        }
+    }
+}
+
+impl<Meta> TryFrom<LoopBlock<Meta>> for Vec<u8>
+        where Meta: Clone + std::fmt::Debug + MetaInstructionTrait, {
+    type Error = Vec<AssemblerError>;
 
+    fn try_from(value: LoopBlock<Meta>) -> Result<Self, Self::Error> {
+        let mut errs: Self::Error = Vec::new();
+
+        let jump: Result<Vec<u8>, Self::Error> = value.footer().and_then(|footer| footer.try_into());
         let mut out: Vec<u8> = value.inner.try_into()?;
         
         match jump {
@@ -118,7 +150,7 @@ impl<Meta> TryFrom<LoopBlock<Meta>> for Vec<u8>
             }
         }
 
-        if errs.len() > 0 {
+        if !errs.is_empty() {
             Err(errs)
         } else {
             Ok(out)
@@ -164,6 +196,10 @@ impl<Meta> BlockAssembler<Meta> for LoopBlock<Meta>
         self.inner.loop_block(condition);
         self
     }
+
+    fn rom_bank(&self) -> u16 {
+        self.inner.rom_bank()
+    }
 }
 
 impl<Meta> MacroAssembler<Meta, AssemblerError, ConstAllocError> for LoopBlock<Meta>
@@ -172,6 +208,14 @@ impl<Meta> MacroAssembler<Meta, AssemblerError, ConstAllocError> for LoopBlock<M
         self.inner.new_stored_const(data)
     }
 
+    fn new_stored_const_aligned(&mut self, data: &[u8], align: u16) -> Result<StoredConstant, AssemblerError> {
+        self.inner.new_stored_const_aligned(data, align)
+    }
+
+    fn new_rom0_const(&mut self, data: &[u8]) -> Result<StoredConstant, AssemblerError> {
+        self.inner.new_rom0_const(data)
+    }
+
     fn new_inline_const_r8(&mut self, data: u8) -> Constant {
         self.inner.new_inline_const_r8(data)
     }
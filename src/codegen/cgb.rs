@@ -1,70 +1,424 @@
-use std::cell::RefCell;
-use std::io::{Seek, Write};
+use std::cell::{RefCell, RefMut};
+use std::io::{self, Write};
 use std::rc::Rc;
-use std::{fs::File, io};
 
-use super::allocator::{ConstAllocError, ConstAllocator};
+use super::allocator::{AllocGroup, Allocator, ConstAllocError, ConstAllocator};
 use super::assembler::{BlockAssembler, Context};
+use super::block::BlockTrait;
+use super::listing::ListingCollector;
 use super::meta_instr::MetaInstruction;
-use super::variables::{Constant, IdInner, StoredConstant, Variabler};
-use super::{Assembler, AssemblerError, BasicBlock, LoopBlock, LoopCondition, MacroAssembler};
+use super::state::{Model, SystemState};
+use super::symbols::{region_name, SymbolCollector, SymbolTable};
+use super::variables::{Constant, IdInner, SramVariable, StoredConstant, Variabler};
+use super::{Assembler, AssemblerError, BasicBlock, Block, BuildError, LoopBlock, LoopCondition, MacroAssembler, Variable};
+use crate::cartridge::{BankedAddr, HeaderBuilder, Mbc, RomImage, CODE_BASE, FAR_CALL, FAR_JP, JP_HL, ROM0_CONST_BASE, ROMX_BASE, ROM_BANK_SELECT, ROM_BANK_SHADOW, ROM_BANK_SIZE};
 use crate::cpu::instructions::Instruction;
-use crate::cpu::Condition;
-use crate::ppu::{palettes::Color, TilemapSelector};
+use crate::cpu::interrupts::Interrupt;
+use crate::cpu::{Condition, GpRegister, RegisterPair, StackPair};
+use crate::ppu::TilemapSelector;
 
+/// User code for each interrupt, see [Cgb::interrupt]
 #[derive(Clone, Debug, PartialEq, Eq, Default)]
 pub struct InterruptHandlers {
-    
+    handlers: [Option<BasicBlock<MetaInstruction>>; 5],
+    /// Run [MacroAssembler::oam_dma] at the start of the VBlank handler
+    pub oam_dma: bool,
+    /// Run [MacroAssembler::step_sfx] in the VBlank handler, after any OAM DMA
+    pub sfx: bool,
+    /// Run [MacroAssembler::step_fade] in the VBlank handler
+    pub fade: bool,
+    /// Run [MacroAssembler::scroll_by] with these deltas in the VBlank handler
+    pub scroll: Option<(Variable, Variable)>,
+}
+
+impl InterruptHandlers {
+    pub fn get(&self, interrupt: Interrupt) -> Option<&BasicBlock<MetaInstruction>> {
+        self.handlers[interrupt as usize].as_ref()
+    }
+
+    /// Handler for `interrupt`, created empty if it doesn't exist yet
+    pub fn get_or_insert(&mut self, interrupt: Interrupt, allocator: Rc<RefCell<ConstAllocator>>) -> &mut BasicBlock<MetaInstruction> {
+        self.handlers[interrupt as usize].get_or_insert_with(|| BasicBlock::new(allocator))
+    }
+
+    /// Wraps every registered handler in code saving and restoring all registers, ending in `reti`
+    pub fn into_wrapped(mut self, allocator: Rc<RefCell<ConstAllocator>>) -> Result<Vec<(Interrupt, BasicBlock<MetaInstruction>)>, AssemblerError> {
+        if self.oam_dma || self.sfx || self.fade || self.scroll.is_some() {
+            self.get_or_insert(Interrupt::VBlank, allocator);
+        }
+
+        let mut out = Vec::new();
+        for (interrupt, handler) in Interrupt::ALL.into_iter().zip(self.handlers) {
+            let Some(handler) = handler else {
+                continue;
+            };
+
+            let mut wrapper = BasicBlock::new(handler.allocator());
+            wrapper.push(StackPair::AF)
+                .push(StackPair::BC)
+                .push(StackPair::DE)
+                .push(StackPair::HL);
+
+            if interrupt == Interrupt::VBlank && self.oam_dma {
+                // OAM DMA has to finish inside VBlank, so it goes first
+                wrapper.oam_dma()?;
+            }
+
+            if interrupt == Interrupt::VBlank && self.sfx {
+                wrapper.step_sfx()?;
+            }
+
+            if interrupt == Interrupt::VBlank && self.fade {
+                wrapper.step_fade()?;
+            }
+
+            if let (Interrupt::VBlank, Some((dx, dy))) = (interrupt, &self.scroll) {
+                wrapper.scroll_by(dx, dy)?;
+            }
+
+            wrapper.contents_mut().push(Block::Basic(handler));
+            wrapper.pop(StackPair::HL)
+                .pop(StackPair::DE)
+                .pop(StackPair::BC)
+                .pop(StackPair::AF)
+                .reti();
+
+            out.push((interrupt, wrapper));
+        }
+
+        Ok(out)
+    }
+}
+
+/// Code living in its own ROMX bank, starting at [ROMX_BASE]
+#[derive(Clone, Debug)]
+pub struct BankedSection {
+    pub bank: u16,
+    pub inner: BasicBlock<MetaInstruction>,
+}
+
+impl BankedSection {
+    /// Address to [MacroAssembler::far_call] or [MacroAssembler::far_jp] into
+    pub fn entry(&self) -> BankedAddr {
+        BankedAddr { bank: self.bank, addr: ROMX_BASE }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Cgb {
     inner: BasicBlock<MetaInstruction>,
-    palettes: [[Color; 4]; 8],
+    sections: Vec<BankedSection>,
+    header: HeaderBuilder,
+    fill: u8,
     tilemap: TilemapSelector,
     handlers: InterruptHandlers,
 }
 
 impl Cgb {
     pub fn new() -> Self {
-        let mut allocator = ConstAllocator::default();
-        allocator.constants.offset = 0x0800;
-        allocator.variables.offset = 0xc000;
+        Self::with_mbc(Mbc::RomOnly)
+    }
+
+    pub fn with_mbc(mbc: Mbc) -> Self {
+        Self::for_model(mbc, Model::Cgb)
+    }
+
+    /// Shared setup for [Cgb] and [Dmg](super::dmg::Dmg), which only differ in what the state allows
+    pub(super) fn for_model(mbc: Mbc, model: Model) -> Self {
+        let defaults = ConstAllocator::default();
+        let allocator = ConstAllocator {
+            mbc,
+            constants: AllocGroup { offset: ROM0_CONST_BASE, len: ROMX_BASE - ROM0_CONST_BASE, ..defaults.constants },
+            variables: AllocGroup { offset: 0xc000, ..defaults.variables },
+            state: SystemState { model, ..Default::default() },
+            ..defaults
+        };
+
+        let mut inner: BasicBlock<MetaInstruction> = BasicBlock::new(Rc::new(RefCell::new(allocator)));
 
-        let inner: BasicBlock<MetaInstruction> = BasicBlock::new(Rc::new(RefCell::new(allocator)));
+        // the stack starts out in HRAM, so move it to the top of WRAM
+        inner.ld_r16_imm(RegisterPair::SP, 0xe000);
+
+        if mbc.is_banked() {
+            // MBCs power on with bank 1 mapped
+            inner.ld_r8_imm(GpRegister::A, 1)
+                .ldh_from_a(ROM_BANK_SHADOW as u8);
+        }
 
         Self {
             inner,
-            palettes: [[Color::WHITE; 4]; 8],
+            sections: Vec::new(),
+            header: Default::default(),
+            fill: 0xff,
             tilemap: TilemapSelector::Tilemap9800,
             handlers: Default::default(),
         }
     }
 
-    pub fn save(mut self, file: &mut File) -> io::Result<()>{
-        // set CGB mode
-        file.seek(io::SeekFrom::Start(0x143))?;
-        file.write_all(&[0x80])?;
+    /// Reserves a fresh ROMX bank for code, returning its bank number
+    pub fn new_section(&mut self) -> Result<u16, AssemblerError> {
+        let bank = self.inner.allocator.borrow_mut().alloc_bank()?;
+        let mut inner = BasicBlock::new(self.inner.allocator.clone());
+        inner.rom_bank = bank;
+        self.sections.push(BankedSection { bank, inner });
+
+        Ok(bank)
+    }
+
+    /// Code builder for the section in ROM bank `bank`
+    pub fn section(&mut self, bank: u16) -> Option<&mut BankedSection> {
+        self.sections.iter_mut().find(|section| section.bank == bank)
+    }
+
+    /// ROM0 routines backing [MacroAssembler::far_call] and [MacroAssembler::far_jp]
+    fn trampolines() -> Vec<Instruction<MetaInstruction>> {
+        use Instruction::*;
+        use GpRegister::*;
+
+        let shadow = ROM_BANK_SHADOW as u8;
+
+        vec![
+            // FAR_CALL: a = bank, hl = target
+            Push(StackPair::BC),
+            LdR8FromR8(B, A),
+            LdhToA(shadow),
+            LdR8FromR8(C, A),
+            LdR8FromR8(A, B),
+            LdhFromA(shadow),
+            LdAToInd(ROM_BANK_SELECT),
+            LdR8FromR8(A, C),
+            Pop(StackPair::BC),
+            Push(StackPair::AF),
+            Call(Condition::Always, JP_HL),
+            Pop(StackPair::AF),
+            LdhFromA(shadow),
+            LdAToInd(ROM_BANK_SELECT),
+            Ret(Condition::Always),
+            // JP_HL
+            JpHl,
+            // FAR_JP: a = bank, hl = target
+            LdhFromA(shadow),
+            LdAToInd(ROM_BANK_SELECT),
+            JpHl,
+        ]
+    }
+
+    /// Names a WRAM or HRAM variable in the `.sym` file
+    pub fn name_var(&mut self, var: &Variable, name: &str) -> Result<&mut Self, AssemblerError> {
+        match var {
+            Variable::Memory(var) => {
+                self.symbols_mut().rename(var.addr.into(), name);
+                Ok(self)
+            }
+            _ => Err(AssemblerError::ArgumentError),
+        }
+    }
+
+    /// Names a cartridge RAM variable in the `.sym` file
+    pub fn name_sram_var(&mut self, var: &SramVariable, name: &str) -> &mut Self {
+        self.symbols_mut().rename(BankedAddr { bank: var.bank, addr: var.inner.addr }, name);
+        self
+    }
+
+    /// Names a stored constant in the `.sym` file
+    pub fn name_const(&mut self, constant: &StoredConstant, name: &str) -> &mut Self {
+        self.symbols_mut().rename(BankedAddr { bank: constant.bank, addr: constant.addr }, name);
+        self
+    }
+
+    fn symbols_mut(&mut self) -> RefMut<'_, SymbolTable> {
+        RefMut::map(self.inner.allocator.borrow_mut(), |allocator| &mut allocator.symbols)
+    }
+
+    /// Builds the handler of `interrupt` with `inner`, appending to it if it already has code
+    /// 
+    /// Handlers live in ROM0 after the main code and are jumped to from their vector, with every register saved beforehand
+    /// and restored before returning with `reti`. The interrupt still has to be enabled in IE
+    /// ([MacroAssembler::set_interrupt_enable]) and with `ei` to fire.
+    /// 
+    /// A handler can run at any point of the main code, so it starts with an unknown LCD state,
+    /// and whatever it assumes about the LCD (see [MacroAssembler::assume_vblank]) is forgotten afterwards
+    pub fn interrupt<F>(&mut self, interrupt: Interrupt, inner: F) -> Result<&mut Self, AssemblerError>
+            where F: FnOnce(&mut BasicBlock<MetaInstruction>) -> Result<(), AssemblerError> {
+        let allocator = self.inner.allocator.clone();
+        let lcd = std::mem::take(&mut allocator.borrow_mut().state_mut().lcd);
+
+        let out = inner(self.handlers.get_or_insert(interrupt, allocator.clone()));
+        allocator.borrow_mut().state_mut().lcd = lcd;
+        out?;
+
+        Ok(self)
+    }
+
+    /// Copies the shadow OAM into OAM at the start of every VBlank, see [MacroAssembler::init_oam_dma]
+    /// 
+    /// The VBlank interrupt still has to be enabled for this to run
+    pub fn schedule_oam_dma(&mut self) -> &mut Self {
+        self.handlers.oam_dma = true;
+        self
+    }
+
+    /// Advances the playing sound effect by one frame every VBlank, see [MacroAssembler::play_sfx]
+    /// 
+    /// The VBlank interrupt still has to be enabled for this to run
+    pub fn schedule_sfx(&mut self) -> &mut Self {
+        self.handlers.sfx = true;
+        self
+    }
+
+    /// Scrolls the background by the signed deltas in `dx` and `dy` every VBlank, see [MacroAssembler::scroll_by]
+    /// 
+    /// Both have to be 8-bit variables in memory, since the handler can run at any point of the main code.
+    /// The VBlank interrupt still has to be enabled for this to run
+    pub fn schedule_scroll(&mut self, dx: &Variable, dy: &Variable) -> Result<&mut Self, AssemblerError> {
+        let in_memory = |var: &Variable| matches!(var, Variable::Memory(var) if var.len == 1);
+        if !in_memory(dx) || !in_memory(dy) {
+            Err(AssemblerError::ArgumentError)?
+        }
+
+        self.handlers.scroll = Some((dx.clone(), dy.clone()));
+        Ok(self)
+    }
+
+    /// Writes the next frame of the running palette fade every VBlank, see [MacroAssembler::start_fade]
+    /// 
+    /// The VBlank interrupt still has to be enabled for this to run
+    pub fn schedule_fade(&mut self) -> &mut Self {
+        self.handlers.fade = true;
+        self
+    }
+
+    /// Cartridge header fields, anything left unset is derived from the ROM layout
+    pub fn header(&mut self) -> &mut HeaderBuilder {
+        &mut self.header
+    }
+
+    /// Byte used to pad out unused ROM space, `0xff` (`rst $38`) by default
+    pub fn fill_byte(&mut self, fill: u8) -> &mut Self {
+        self.fill = fill;
+        self
+    }
+
+    /// Lays the program out into a ROM image, padded to the size in the header
+    /// 
+    /// Works on copies of the code, so the system can keep being extended and built again
+    pub fn build(&self) -> Result<RomImage, BuildError> {
+        let (mbc, banks, ram_banks) = {
+            let allocator = self.inner.allocator.borrow();
+            (allocator.mbc, allocator.bank_count().next_power_of_two(), allocator.sram_banks.len() as u16)
+        };
+
+        let header = self.header.build(mbc, banks, ram_banks)?;
+        // the header builder rejects size codes past 0x08, so this can't overflow
+        let size = (2 * ROM_BANK_SIZE) << header.rom_size;
+        let needed = banks as usize * ROM_BANK_SIZE;
+        if size < needed {
+            Err(BuildError::RomTooSmall { size, needed })?
+        }
+
+        let mut rom = vec![self.fill; size];
+        let mut write = |offset: usize, bytes: &[u8]| rom[offset..offset + bytes.len()].copy_from_slice(bytes);
 
         // jump to main code
-        let trampoline: Vec<u8> = Instruction::<MetaInstruction>::Jp(Condition::Always, 0x150).into();
-        file.seek(io::SeekFrom::Start(0x100))?;
-        file.write_all(&trampoline)?;
+        let trampoline: Vec<u8> = Instruction::<MetaInstruction>::Jp(Condition::Always, CODE_BASE).into();
+        write(0x100, &trampoline);
+
+        let far: Vec<u8> = Self::trampolines().into_iter().flat_map(Vec::<u8>::from).collect();
+        debug_assert_eq!(FAR_CALL as usize + far.len(), FAR_JP as usize + 6);
+        write(FAR_CALL as usize, &far);
+
+        let mut symbols = self.inner.allocator.borrow().symbols.clone();
+        symbols.add(BankedAddr::from(0x100), "entry")
+            .add(FAR_CALL.into(), "far_call")
+            .add(JP_HL.into(), "jp_hl")
+            .add(FAR_JP.into(), "far_jp");
+        if mbc.is_banked() {
+            symbols.add(ROM_BANK_SHADOW.into(), "rom_bank_shadow");
+        }
 
-        // TODO: Fix constant allocation
-        for (constant, bytes) in self.inner.gather_consts() {
+        // interrupt handlers follow main code in ROM0
+        let allocator = self.inner.allocator.clone();
+        let mut rom0 = vec![("main".to_owned(), None, self.inner.clone())];
+        for (interrupt, handler) in self.handlers.clone().into_wrapped(allocator)? {
+            rom0.push((format!("{}_handler", interrupt.name()), Some(interrupt), handler));
+        }
+
+        let mut consts: Vec<(Constant, Vec<u8>)> = rom0.iter_mut().flat_map(|(_, _, block)| block.gather_consts()).collect();
+        let mut sections = self.sections.clone();
+        for section in sections.iter_mut() {
+            consts.extend(section.inner.gather_consts());
+        }
+
+        for (constant, bytes) in consts {
             if let Constant::Addr(constant) = constant {
-                file.seek(io::SeekFrom::Start(constant.addr as u64))?;
-                file.write_all(&bytes)?;
+                let addr = BankedAddr { bank: constant.bank, addr: constant.addr };
+                symbols.add_default(addr, region_name(addr));
+                write(addr.rom_offset(), &bytes);
+            }
+        }
+
+        let mut collector = SymbolCollector::new(0);
+        let mut listing = ListingCollector::new(0);
+        let mut starts = Vec::with_capacity(rom0.len());
+        let mut addr = CODE_BASE;
+        for (name, _, block) in rom0.iter() {
+            starts.push(addr);
+            block.walk(&mut vec![name.clone()], addr, &mut collector)?;
+            addr = block.walk(&mut vec![name.clone()], addr, &mut listing)?;
+        }
+
+        for section in sections.iter() {
+            collector.bank = section.bank;
+            listing.bank = section.bank;
+            let mut path = vec![format!("section_{:02x}", section.bank)];
+            section.inner.walk(&mut path, ROMX_BASE, &mut collector)?;
+            section.inner.walk(&mut path, ROMX_BASE, &mut listing)?;
+        }
+        symbols.extend(collector.symbols);
+
+        let max = (ROM0_CONST_BASE - CODE_BASE) as usize;
+        let len = (addr - CODE_BASE) as usize;
+        if len > max {
+            Err(BuildError::CodeTooBig { len, max })?
+        }
+
+        // unused vectors just return, in case an interrupt gets enabled without a handler
+        for interrupt in Interrupt::ALL {
+            let handler = rom0.iter().zip(starts.iter()).find(|((_, handler, _), _)| *handler == Some(interrupt));
+            let vector = match handler {
+                Some((_, &start)) => Instruction::<MetaInstruction>::Jp(Condition::Always, start),
+                None => Instruction::Reti,
+            };
+
+            let vector: Vec<u8> = vector.into();
+            write(interrupt.vector() as usize, &vector);
+            symbols.add(interrupt.vector().into(), format!("{}_vector", interrupt.name()));
+        }
+
+        for ((_, _, block), start) in rom0.into_iter().zip(starts) {
+            let output: Vec<u8> = block.try_into()?;
+            write(start as usize, &output);
+        }
+
+        for section in sections {
+            let offset = section.entry().rom_offset();
+            let output: Vec<u8> = section.inner.try_into()?;
+            if output.len() > ROM_BANK_SIZE {
+                Err(BuildError::SectionTooBig { bank: section.bank, len: output.len() })?
             }
+
+            write(offset, &output);
         }
 
-        let output: Vec<u8> = self.inner.try_into().expect("Blorp");
-        file.seek(io::SeekFrom::Start(0x150))?;
-        file.write_all(&output)?;
+        header.write_to(&mut rom);
 
+        Ok(RomImage { bytes: rom, symbols, listing: listing.listing })
+    }
 
-        io::Result::Ok(())
+    /// Builds the ROM and writes it to `out`
+    pub fn save<W>(&self, out: &mut W) -> io::Result<()>
+            where W: Write {
+        self.build()?.write_to(out)
     }
 }
 
@@ -104,6 +458,10 @@ impl BlockAssembler<MetaInstruction> for Cgb {
     fn loop_block(&mut self, condition: LoopCondition) -> &mut LoopBlock<MetaInstruction> {
         self.inner.loop_block(condition)
     }
+
+    fn rom_bank(&self) -> u16 {
+        self.inner.rom_bank()
+    }
 }
 
 impl MacroAssembler<MetaInstruction, AssemblerError, ConstAllocError> for Cgb {
@@ -111,6 +469,14 @@ impl MacroAssembler<MetaInstruction, AssemblerError, ConstAllocError> for Cgb {
         self.inner.new_stored_const(data)
     }
 
+    fn new_stored_const_aligned(&mut self, data: &[u8], align: u16) -> Result<StoredConstant, AssemblerError> {
+        self.inner.new_stored_const_aligned(data, align)
+    }
+
+    fn new_rom0_const(&mut self, data: &[u8]) -> Result<StoredConstant, AssemblerError> {
+        self.inner.new_rom0_const(data)
+    }
+
     fn new_inline_const_r8(&mut self, data: u8) -> Constant {
         self.inner.new_inline_const_r8(data)
     }
@@ -0,0 +1,147 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+
+use super::allocator::{ConstAllocError, ConstAllocator};
+use super::assembler::{BlockAssembler, Context};
+use super::cgb::Cgb;
+use super::meta_instr::MetaInstruction;
+use super::state::Model;
+use super::variables::{Constant, IdInner, StoredConstant, Variabler};
+use super::{Assembler, AssemblerError, BasicBlock, BuildError, LoopBlock, LoopCondition, MacroAssembler};
+use crate::cartridge::{CgbFlag, Mbc, RomImage};
+use crate::cpu::instructions::Instruction;
+
+/// Program builder for the original Game Boy
+/// 
+/// Lays out ROMs exactly like [Cgb], which it derefs to for sections, interrupt handlers and the header.
+/// CGB only helpers fail with [AssemblerError::CgbOnly], and the header is marked as not using CGB features
+#[derive(Clone, Debug)]
+pub struct Dmg {
+    inner: Cgb,
+}
+
+impl Dmg {
+    pub fn new() -> Self {
+        Self::with_mbc(Mbc::RomOnly)
+    }
+
+    pub fn with_mbc(mbc: Mbc) -> Self {
+        let mut inner = Cgb::for_model(mbc, Model::Dmg);
+        inner.header().cgb_flag(CgbFlag::Dmg);
+
+        Self { inner }
+    }
+
+    /// Lays the program out into a ROM image, see [Cgb::build]
+    pub fn build(&self) -> Result<RomImage, BuildError> {
+        self.inner.build()
+    }
+
+    /// Builds the ROM and writes it to `out`
+    pub fn save<W>(&self, out: &mut W) -> io::Result<()>
+            where W: Write {
+        self.inner.save(out)
+    }
+}
+
+impl Default for Dmg {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for Dmg {
+    type Target = Cgb;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for Dmg {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl Assembler<MetaInstruction> for Dmg {
+    fn push_instruction(&mut self, instruction: Instruction<MetaInstruction>) {
+        self.inner.push_instruction(instruction)
+    }
+
+    fn push_buf(&mut self, buf: &[Instruction<MetaInstruction>]) {
+        self.inner.push_buf(buf)
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+impl Variabler<MetaInstruction, AssemblerError, ConstAllocError> for Dmg {
+    type Alloc = ConstAllocator;
+
+    fn new_var(&mut self, len: u16) -> super::Variable {
+        self.inner.new_var(len)
+    }
+
+    fn allocator(&self) -> Rc<RefCell<Self::Alloc>> {
+        self.inner.allocator()
+    }
+}
+
+impl BlockAssembler<MetaInstruction> for Dmg {
+    fn basic_block(&mut self) -> &mut BasicBlock<MetaInstruction> {
+        self.inner.basic_block()
+    }
+
+    fn loop_block(&mut self, condition: LoopCondition) -> &mut LoopBlock<MetaInstruction> {
+        self.inner.loop_block(condition)
+    }
+
+    fn rom_bank(&self) -> u16 {
+        self.inner.rom_bank()
+    }
+}
+
+impl MacroAssembler<MetaInstruction, AssemblerError, ConstAllocError> for Dmg {
+    fn new_stored_const(&mut self, data: &[u8]) -> Result<StoredConstant, AssemblerError> {
+        self.inner.new_stored_const(data)
+    }
+
+    fn new_stored_const_aligned(&mut self, data: &[u8], align: u16) -> Result<StoredConstant, AssemblerError> {
+        self.inner.new_stored_const_aligned(data, align)
+    }
+
+    fn new_rom0_const(&mut self, data: &[u8]) -> Result<StoredConstant, AssemblerError> {
+        self.inner.new_rom0_const(data)
+    }
+
+    fn new_inline_const_r8(&mut self, data: u8) -> Constant {
+        self.inner.new_inline_const_r8(data)
+    }
+
+    fn new_inline_const_r16(&mut self, data: u16) -> Constant {
+        self.inner.new_inline_const_r16(data)
+    }
+
+    fn evaluate_meta(&mut self) -> Result<(), AssemblerError> {
+        self.inner.evaluate_meta()
+    }
+
+    fn gather_consts(&mut self) -> Vec<(Constant, Vec<u8>)> {
+        self.inner.gather_consts()
+    }
+}
+
+impl Context for Dmg {
+    fn next_id(&self) -> IdInner {
+        self.inner.next_id()
+    }
+
+    fn next_id_mut(&mut self) -> &mut IdInner {
+        self.inner.next_id_mut()
+    }
+}
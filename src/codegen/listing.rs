@@ -0,0 +1,93 @@
+use std::io::{self, Write};
+
+use crate::cartridge::BankedAddr;
+use crate::cpu::instructions::Instruction;
+use crate::memory::Addr;
+
+use super::block::BlockVisitor;
+use super::meta_instr::MetaInstructionTrait;
+
+/// A single emitted instruction (or label) in a [Listing]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListingLine {
+    pub addr: BankedAddr,
+    pub bytes: Vec<u8>,
+    pub mnemonic: String,
+    /// Blocks enclosing the instruction, outermost first
+    pub path: Vec<String>,
+    /// Macros and [MetaInstruction](super::meta_instr::MetaInstruction)s the instruction was lowered from, outermost first
+    pub lowered_from: Vec<String>,
+}
+
+/// Every instruction in a ROM with its final address, bytes, and where it came from
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct Listing {
+    pub lines: Vec<ListingLine>,
+}
+
+impl Listing {
+    pub fn extend(&mut self, other: Listing) -> &mut Self {
+        self.lines.extend(other.lines);
+        self
+    }
+
+    pub fn write_to<W>(&self, out: &mut W) -> io::Result<()>
+            where W: Write {
+        for line in self.lines.iter() {
+            let BankedAddr { bank, addr } = line.addr;
+            let bytes: Vec<String> = line.bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+            write!(out, "{:02x}:{:04x}  {:<9} {:<24} {}", bank, addr, bytes.join(" "), line.mnemonic, line.path.join(" > "))?;
+
+            if !line.lowered_from.is_empty() {
+                write!(out, "  ; {}", line.lowered_from.join(" > "))?;
+            }
+
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// [BlockVisitor] recording every instruction into a [Listing]
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct ListingCollector {
+    pub bank: u16,
+    pub listing: Listing,
+    markers: Vec<String>,
+}
+
+impl ListingCollector {
+    pub fn new(bank: u16) -> Self {
+        Self {
+            bank,
+            ..Default::default()
+        }
+    }
+}
+
+impl<Meta> BlockVisitor<Meta> for ListingCollector
+        where Meta: Clone + std::fmt::Debug + std::fmt::Display + PartialEq + MetaInstructionTrait, {
+    fn instruction(&mut self, path: &[String], addr: Addr, instruction: &Instruction<Meta>) {
+        if let Instruction::Meta(meta) = instruction {
+            if meta.is_marker() {
+                if *meta == Meta::end() {
+                    self.markers.pop();
+                } else {
+                    self.markers.push(meta.to_string());
+                }
+
+                return;
+            }
+        }
+
+        self.listing.lines.push(ListingLine {
+            addr: BankedAddr { bank: self.bank, addr },
+            bytes: instruction.clone().into(),
+            mnemonic: instruction.to_string(),
+            path: path.to_vec(),
+            lowered_from: self.markers.clone(),
+        });
+    }
+}
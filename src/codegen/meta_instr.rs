@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 use super::{variables::Constant, Variable};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -26,6 +28,14 @@ pub trait MetaInstructionTrait {
     fn inc_var(var: Variable) -> Self;
     fn sub_var(lhs: Variable, rhs: Variable) -> Self;
     fn dec_var(var: Variable) -> Self;
+    /// Marks the start of the instructions `meta` was lowered into
+    fn lowered(meta: Self) -> Self;
+    /// Marks the start of the instructions emitted by the macro `name`
+    fn macro_call(name: &'static str) -> Self;
+    /// Closes the innermost [MetaInstructionTrait::lowered] or [MetaInstructionTrait::macro_call]
+    fn end() -> Self;
+    /// Markers only annotate the instruction stream for listings, they're never emitted
+    fn is_marker(&self) -> bool;
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -37,6 +47,9 @@ pub enum MetaInstruction {
     VarInc { var: Variable },
     VarSub { lhs: Variable, rhs: Variable },
     VarDec { var: Variable },
+    Lowered(Box<MetaInstruction>),
+    Macro(&'static str),
+    End,
 }
 
 impl MetaInstructionTrait for MetaInstruction {
@@ -67,5 +80,49 @@ impl MetaInstructionTrait for MetaInstruction {
     fn dec_var(var: Variable) -> Self {
         Self::VarDec { var }
     }
+
+    fn lowered(meta: Self) -> Self {
+        Self::Lowered(Box::new(meta))
+    }
+
+    fn macro_call(name: &'static str) -> Self {
+        Self::Macro(name)
+    }
+
+    fn end() -> Self {
+        Self::End
+    }
+
+    fn is_marker(&self) -> bool {
+        matches!(self, Self::Lowered(_) | Self::Macro(_) | Self::End)
+    }
+}
+
+impl Display for VarOrConst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Var(var) => write!(f, "{}", var),
+            Self::Const(Constant::Inline8(imm)) => write!(f, "${:02x}", imm),
+            Self::Const(Constant::Inline16(imm)) => write!(f, "${:04x}", imm),
+            Self::Const(Constant::Addr(constant)) => write!(f, "${:02x}:{:04x}", constant.bank, constant.addr),
+        }
+    }
+}
+
+impl Display for MetaInstruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::VarSet { dest, src } => write!(f, "set_var({}, {})", dest, src),
+            Self::VarFromInd { dest, src } => write!(f, "var_from_ind({}, {})", dest, src),
+            Self::VarToInd { dest, src } => write!(f, "var_to_ind({}, {})", dest, src),
+            Self::VarAdd { lhs, rhs } => write!(f, "add_var({}, {})", lhs, rhs),
+            Self::VarInc { var } => write!(f, "inc_var({})", var),
+            Self::VarSub { lhs, rhs } => write!(f, "sub_var({}, {})", lhs, rhs),
+            Self::VarDec { var } => write!(f, "dec_var({})", var),
+            Self::Lowered(meta) => write!(f, "{}", meta),
+            Self::Macro(name) => write!(f, "{}", name),
+            Self::End => write!(f, "end"),
+        }
+    }
 }
 
@@ -0,0 +1,80 @@
+use crate::cpu::CpuSpeed;
+use crate::joypad::Joypad;
+use crate::memory::Addr;
+use crate::ppu::objects::ObjSize;
+use crate::ppu::VramBank;
+use crate::timer::TimerConfig;
+
+pub use crate::model::Model;
+pub use crate::ppu::palettes::Palettes;
+
+/// What the generated code is known to have done to the hardware at the current point in the program
+/// 
+/// Tracked linearly as code is emitted, so anything set inside a loop or a handler is assumed to hold afterwards.
+/// The exception is [SystemState::lcd], which [Cgb::interrupt](crate::Cgb::interrupt) restores after building a handler
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct SystemState {
+    /// Hardware the program targets, set when the [Cgb](crate::Cgb) or [Dmg](crate::Dmg) is created
+    pub model: Model,
+    pub lcd: LcdState,
+    /// Set by [MacroAssembler::set_speed](super::MacroAssembler::set_speed)
+    pub speed: CpuSpeed,
+    pub vram_bank: VramBank,
+    /// Set by [MacroAssembler::set_obj_size](super::MacroAssembler::set_obj_size)
+    pub obj_size: ObjSize,
+    pub palettes: Palettes,
+    /// Set up by [MacroAssembler::init_oam_dma](super::MacroAssembler::init_oam_dma)
+    pub oam_dma: Option<OamDma>,
+    /// Set up by [MacroAssembler::init_joypad](super::MacroAssembler::init_joypad)
+    pub joypad: Option<Joypad>,
+    /// Set up by [MacroAssembler::init_sound](super::MacroAssembler::init_sound)
+    pub sound: Option<Sound>,
+    /// Set up by [MacroAssembler::init_fade](super::MacroAssembler::init_fade)
+    pub fade: Option<Fade>,
+    /// Last config passed to [MacroAssembler::start_timer](super::MacroAssembler::start_timer)
+    pub timer: Option<TimerConfig>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum LcdState {
+    /// Nothing has touched LCDC yet
+    #[default]
+    Unknown,
+    /// On, and the PPU could be anywhere in the frame
+    On,
+    /// On, and known to be in VBlank
+    VBlank,
+    Off,
+}
+
+impl LcdState {
+    /// Whether clearing LCDC bit 7 right now can't damage the hardware
+    pub fn can_disable(&self) -> bool {
+        matches!(self, Self::VBlank | Self::Off)
+    }
+}
+
+/// Where the sound effect stepper keeps its place
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sound {
+    /// Little endian pointer in WRAM to the next frame of the playing [SoundEffect](crate::apu::SoundEffect),
+    /// a high byte of 0 means nothing is playing
+    pub pointer: Addr,
+}
+
+/// Where the shadow OAM buffer and the DMA routine copying it into OAM live
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OamDma {
+    /// 0x100-aligned buffer in WRAM, laid out exactly like OAM
+    pub shadow: Addr,
+    /// DMA routine in HRAM, since the CPU can only access HRAM while the transfer runs
+    pub routine: Addr,
+}
+
+/// Where the palette fade stepper keeps its place
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fade {
+    /// Little endian pointer in WRAM to the next frame of the running [PaletteFade](crate::ppu::fade::PaletteFade),
+    /// a high byte of 0 means no fade is running
+    pub pointer: Addr,
+}
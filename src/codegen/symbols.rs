@@ -0,0 +1,136 @@
+use std::io::{self, Write};
+
+use crate::cartridge::BankedAddr;
+use crate::cpu::instructions::Instruction;
+use crate::memory::Addr;
+
+use super::block::BlockVisitor;
+use super::meta_instr::MetaInstructionTrait;
+
+/// A named address, as shown by emulator debuggers
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub addr: BankedAddr,
+    pub name: String,
+}
+
+/// Symbols for a ROM, written out in the `bank:addr name` `.sym` format understood by BGB, SameBoy and Emulicious
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    /// Adds a symbol, even if the address already has one
+    pub fn add(&mut self, addr: BankedAddr, name: impl Into<String>) -> &mut Self {
+        self.symbols.push(Symbol { addr, name: sanitize(&name.into()) });
+        self
+    }
+
+    /// Adds a symbol only if nothing is named at that address yet
+    ///
+    /// Used for generated names, so reused allocations don't pile up duplicates
+    pub fn add_default(&mut self, addr: BankedAddr, name: impl Into<String>) -> &mut Self {
+        if !self.symbols.iter().any(|symbol| symbol.addr == addr) {
+            self.add(addr, name);
+        }
+
+        self
+    }
+
+    /// Replaces every name at `addr` with `name`
+    pub fn rename(&mut self, addr: BankedAddr, name: impl Into<String>) -> &mut Self {
+        self.symbols.retain(|symbol| symbol.addr != addr);
+        self.add(addr, name)
+    }
+
+    pub fn get(&self, addr: BankedAddr) -> impl Iterator<Item = &str> {
+        self.symbols.iter()
+            .filter(move |symbol| symbol.addr == addr)
+            .map(|symbol| symbol.name.as_str())
+    }
+
+    pub fn extend(&mut self, other: SymbolTable) -> &mut Self {
+        self.symbols.extend(other.symbols);
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Writes the table sorted by bank and address
+    pub fn write_to<W>(&self, out: &mut W) -> io::Result<()>
+            where W: Write {
+        let mut symbols: Vec<&Symbol> = self.symbols.iter().collect();
+        symbols.sort_by_key(|symbol| symbol.addr);
+
+        for Symbol { addr: BankedAddr { bank, addr }, name } in symbols {
+            writeln!(out, "{bank:02x}:{addr:04x} {name}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// [BlockVisitor] naming block entry points and labels after their path in the block tree
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct SymbolCollector {
+    pub bank: u16,
+    pub symbols: SymbolTable,
+}
+
+impl SymbolCollector {
+    pub fn new(bank: u16) -> Self {
+        Self {
+            bank,
+            symbols: Default::default(),
+        }
+    }
+}
+
+impl<Meta> BlockVisitor<Meta> for SymbolCollector
+        where Meta: Clone + std::fmt::Debug + MetaInstructionTrait, {
+    fn enter(&mut self, path: &[String], addr: Addr) {
+        self.symbols.add(BankedAddr { bank: self.bank, addr }, path_name(path));
+    }
+
+    fn instruction(&mut self, path: &[String], addr: Addr, instruction: &Instruction<Meta>) {
+        if let Instruction::Label(id) = instruction {
+            let name = format!("{}.label_{}", path_name(path), id);
+            self.symbols.add(BankedAddr { bank: self.bank, addr }, name);
+        }
+    }
+}
+
+/// Default name for a variable or constant at `addr`, based on the memory region it's in
+pub fn region_name(addr: BankedAddr) -> String {
+    let BankedAddr { bank, addr } = addr;
+    match addr {
+        0x0000..=0x7fff => format!("const_{bank:02x}_{addr:04x}"),
+        0xa000..=0xbfff => format!("sram_{bank:02x}_{addr:04x}"),
+        0xc000..=0xdfff => format!("wram_{addr:04x}"),
+        0xff80..=0xfffe => format!("hram_{addr:04x}"),
+        _ => format!("mem_{addr:04x}"),
+    }
+}
+
+/// Joins a block path into a single symbol name
+pub fn path_name(path: &[String]) -> String {
+    path.join(".")
+}
+
+/// Label names have to be a single token, and most debuggers choke on anything but identifier characters and `.`
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '_' { c } else { '_' })
+        .collect()
+}
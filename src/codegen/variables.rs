@@ -1,8 +1,8 @@
 use std::{cell::RefCell, fmt::Display, hash::Hash, rc::Rc};
 
-use crate::{codegen::allocator::RegKind, cpu::{CpuFlag, GpRegister, RegisterPair, SplitError, StackPair}, memory::Addr};
+use crate::{cartridge::BankedAddr, codegen::allocator::RegKind, cpu::{CpuFlag, GpRegister, RegisterPair, SplitError, StackPair}, memory::Addr};
 
-use super::{allocator::{AllocErrorTrait, Allocator, RcGpRegister, RcRegVariable, RcRegisterPair}, assembler::{BlockAssembler, ErrorTrait}, meta_instr::{MetaInstructionTrait, VarOrConst}, Assembler, BasicBlock};
+use super::{allocator::{AllocErrorTrait, Allocator, RcGpRegister, RcRegVariable, RcRegisterPair}, assembler::{BlockAssembler, ErrorTrait}, meta_instr::{MetaInstructionTrait, VarOrConst}, Assembler, AssemblerError, BasicBlock};
 
 pub(crate) type IdInner = usize;
 
@@ -25,6 +25,29 @@ impl Display for Id {
     }
 }
 
+impl Display for Variable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unallocated { id, .. } => write!(f, "var_{}", id),
+            Self::Reg(var) => write!(f, "{}", var.inner()),
+            Self::Memory(var) => write!(f, "[${:04x}]", var.addr),
+        }
+    }
+}
+
+impl Display for RawRegVariable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnallocatedR8(id)
+            | Self::UnallocatedR16(id) => write!(f, "var_{}", id),
+            Self::R8 { reg, .. } => write!(f, "{}", reg),
+            Self::R16 { reg_pair, .. } => write!(f, "{}", reg_pair),
+            Self::MemR8 { addr, reg, .. } => write!(f, "{}@[${:04x}]", reg, addr),
+            Self::MemR16 { addr, reg_pair, .. } => write!(f, "{}@[${:04x}]", reg_pair, addr),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Variable {
     Unallocated { len: u16, id: Id },
@@ -42,6 +65,8 @@ pub enum RawVariable {
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct StoredConstant {
     pub id: Id,
+    /// ROM bank the constant lives in, 0 being ROM0
+    pub bank: u16,
     pub addr: Addr,
     pub len: u16,
 }
@@ -72,6 +97,61 @@ pub enum RegVariable {
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct MemoryVariable { pub addr: Addr, pub len: u16, pub id: Id }
 
+/// Variable in cartridge RAM
+/// 
+/// Cartridge RAM has to be enabled before it can be touched, so it's only read and written through the [SramGuard]
+/// [MacroAssembler::with_sram](super::MacroAssembler::with_sram) hands out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SramVariable {
+    pub bank: u16,
+    pub(crate) inner: MemoryVariable,
+}
+
+/// Loads and stores for an [SramVariable] while [MacroAssembler::with_sram](super::MacroAssembler::with_sram) has its bank enabled
+/// 
+/// The variable itself is never handed out, and the guard borrows the block for the closure only,
+/// so nothing can reach cartridge RAM once it's disabled again
+#[derive(Debug)]
+pub struct SramGuard<'scope, Meta>
+        where Meta: Clone + std::fmt::Debug + MetaInstructionTrait, {
+    block: &'scope mut BasicBlock<Meta>,
+    var: MemoryVariable,
+}
+
+impl<'scope, Meta> SramGuard<'scope, Meta>
+        where Meta: Clone + std::fmt::Debug + MetaInstructionTrait, {
+    pub(crate) fn new(block: &'scope mut BasicBlock<Meta>, var: &SramVariable) -> Self {
+        Self { block, var: var.inner }
+    }
+
+    /// Block the code inside the window goes into
+    pub fn block(&mut self) -> &mut BasicBlock<Meta> {
+        self.block
+    }
+
+    /// `ld a, [var + offset]`, errors if `offset` is past the end of the variable
+    pub fn ld_a_from(&mut self, offset: u16) -> Result<&mut Self, AssemblerError> {
+        let addr = self.addr(offset)?;
+        self.block.ld_a_from_ind(addr);
+        Ok(self)
+    }
+
+    /// `ld [var + offset], a`, errors if `offset` is past the end of the variable
+    pub fn ld_a_to(&mut self, offset: u16) -> Result<&mut Self, AssemblerError> {
+        let addr = self.addr(offset)?;
+        self.block.ld_a_to_ind(addr);
+        Ok(self)
+    }
+
+    fn addr(&self, offset: u16) -> Result<Addr, AssemblerError> {
+        if offset >= self.var.len {
+            Err(AssemblerError::ArgumentError)?
+        }
+
+        Ok(self.var.addr + offset)
+    }
+}
+
 impl Variable {
     /// **Prevents this register from being automatically deallocated**
     /// 
@@ -191,7 +271,7 @@ impl From<RegisterPair> for RegSelector {
 }
 
 pub trait Variabler<Meta, Error, AllocError>: Assembler<Meta> + BlockAssembler<Meta>
-        where Error: Clone + std::fmt::Debug + From<SplitError> + From<AllocError> + ErrorTrait,
+        where Error: Clone + std::fmt::Debug + From<SplitError> + From<AllocError> + From<AssemblerError> + ErrorTrait,
             AllocError: Clone + std::fmt::Debug + Into<Error> + AllocErrorTrait,
             Meta: Clone + std::fmt::Debug + MetaInstructionTrait {
     type Alloc: Allocator<AllocError>;
@@ -555,6 +635,7 @@ pub trait Variabler<Meta, Error, AllocError>: Assembler<Meta> + BlockAssembler<M
     }
 
     fn set_var(&mut self, var: &mut Variable, value: &mut VarOrConst) -> Result<&mut Self, Error> {
+        self.meta(Meta::lowered(Meta::set_var(var.clone(), value.clone())));
         let mut new_var: Option<Variable> = None;
         let dest: RcRegVariable = match var {
             Variable::Reg(RegVariable::Rc(var)) => var.clone(),
@@ -702,12 +783,12 @@ pub trait Variabler<Meta, Error, AllocError>: Assembler<Meta> + BlockAssembler<M
             }
         }
 
-
-
+        self.meta(Meta::end());
         Ok(self)
     }
 
     fn dec_var(&mut self, var: &Variable) -> Result<&mut Self, Error> {
+        self.meta(Meta::lowered(Meta::dec_var(var.clone())));
         let reg = self.load_var(var)?;
 
         match reg {
@@ -722,10 +803,12 @@ pub trait Variabler<Meta, Error, AllocError>: Assembler<Meta> + BlockAssembler<M
             }
         };
 
+        self.meta(Meta::end());
         Ok(self)
     }
 
     fn inc_var(&mut self, var: &Variable) -> Result<&mut Self, Error> {
+        self.meta(Meta::lowered(Meta::inc_var(var.clone())));
         let reg = self.load_var(var)?;
 
         match reg {
@@ -740,10 +823,12 @@ pub trait Variabler<Meta, Error, AllocError>: Assembler<Meta> + BlockAssembler<M
             }
         };
 
+        self.meta(Meta::end());
         Ok(self)
     }
 
     fn ld_a_from_var_ind(&mut self, var: &Variable) -> Result<&mut Self, Error> {
+        self.meta(Meta::lowered(Meta::var_from_ind(RawRegVariable::from(GpRegister::A).into(), var.clone())));
         let reg = self.load_var(var)?;
 
         match reg {
@@ -769,6 +854,24 @@ pub trait Variabler<Meta, Error, AllocError>: Assembler<Meta> + BlockAssembler<M
             }
         };
 
+        self.meta(Meta::end());
+        Ok(self)
+    }
+
+    /// Copies the 8-bit `var` into `a`, without tying `a` to the variable
+    fn ld_a_from_var(&mut self, var: &Variable) -> Result<&mut Self, Error> {
+        match var {
+            Variable::Memory(MemoryVariable { addr, len: 1, .. }) => { self.ld_a_from_ind(*addr); },
+            Variable::Reg(reg) => match reg.inner() {
+                RawRegVariable::R8 { reg: GpRegister::A, .. }
+                | RawRegVariable::MemR8 { reg: GpRegister::A, .. } => {},
+                RawRegVariable::R8 { reg, .. }
+                | RawRegVariable::MemR8 { reg, .. } => { self.ld_r8_from_r8(GpRegister::A, reg); },
+                _ => Err(Error::invalid_arg())?,
+            },
+            _ => Err(Error::invalid_arg())?,
+        }
+
         Ok(self)
     }
 
@@ -818,7 +921,7 @@ pub trait Variabler<Meta, Error, AllocError>: Assembler<Meta> + BlockAssembler<M
 
                 let (reg1, reg2) = reg_pair.try_split()?;
                 let buffer = self.basic_block();
-                let _ = buffer.jr_nz_var(&RawRegVariable::from(reg1).into(), imm)?;
+                buffer.jr_nz_var(&RawRegVariable::from(reg1).into(), imm)?;
                 let block_length = buffer.len();
 
             }
@@ -856,14 +959,55 @@ pub trait Variabler<Meta, Error, AllocError>: Assembler<Meta> + BlockAssembler<M
         self.allocator().borrow().reg_is_used(reg.into())
     }
 
-    fn alloc_const(&self, len: u16) -> Result<Addr, AllocError> {
+    fn alloc_const(&self, len: u16) -> Result<BankedAddr, AllocError> {
         self.allocator().borrow_mut().alloc_const(len)
     }
 
+    fn alloc_bank(&self) -> Result<u16, AllocError> {
+        self.allocator().borrow_mut().alloc_bank()
+    }
+
+    fn alloc_sram(&self, len: u16) -> Result<BankedAddr, AllocError> {
+        self.allocator().borrow_mut().alloc_sram(len)
+    }
+
+    /// Allocates a variable in cartridge RAM
+    fn new_sram_var(&mut self, len: u16) -> Result<SramVariable, Error> {
+        let id = match self.new_var(len) {
+            Variable::Unallocated { id, .. } => id,
+            _ => Id::Unset,
+        };
+        let addr = self.alloc_sram(len)?;
+
+        Ok(SramVariable {
+            bank: addr.bank,
+            inner: MemoryVariable { addr: addr.addr, len, id },
+        })
+    }
+
+    /// Allocates a variable in HRAM
+    fn new_hram_var(&mut self, len: u16) -> Result<Variable, Error> {
+        let id = match self.new_var(len) {
+            Variable::Unallocated { id, .. } => id,
+            _ => Id::Unset,
+        };
+        let addr = self.alloc_hram(len)?;
+
+        Ok(Variable::Memory(MemoryVariable { addr, len, id }))
+    }
+
     fn alloc_var(&self, len: u16) -> Result<Addr, AllocError> {
         self.allocator().borrow_mut().alloc_var(len)
     }
 
+    fn alloc_var_aligned(&self, len: u16, align: u16) -> Result<Addr, AllocError> {
+        self.allocator().borrow_mut().alloc_var_aligned(len, align)
+    }
+
+    fn alloc_hram(&self, len: u16) -> Result<Addr, AllocError> {
+        self.allocator().borrow_mut().alloc_hram(len)
+    }
+
     fn dealloc_var(&self, var: Variable) -> Result<(), AllocError> {
         self.allocator().borrow_mut().dealloc_var(var)?;
         Ok(())
@@ -1,7 +1,10 @@
 pub mod instructions;
+pub mod interrupts;
 
 pub use instructions::Condition;
 
+use std::fmt::Display;
+
 use crate::codegen::AssemblerError;
 
 #[repr(u8)]
@@ -21,6 +24,26 @@ pub enum CpuFlag {
     NC, C,
 }
 
+/// CGB CPU speed, switched through KEY1
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum CpuSpeed {
+    /// 4.19 MHz, what every model boots in
+    #[default]
+    Normal,
+    /// 8.39 MHz, CGB only. DIV, the timer and serial double with it, the PPU and APU don't
+    Double,
+}
+
+impl CpuSpeed {
+    /// How many times faster than normal speed the CPU runs
+    pub fn multiplier(&self) -> u32 {
+        match self {
+            Self::Normal => 1,
+            Self::Double => 2,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SplitError {
     StackPointer
@@ -81,4 +104,72 @@ impl TryInto<IndirectPair> for RegisterPair {
             _ => Err(RegConversionError::InvalidIndirectPair)
         }
     }
-}
\ No newline at end of file
+}
+impl Display for GpRegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let out = match self {
+            Self::B => "b",
+            Self::C => "c",
+            Self::D => "d",
+            Self::E => "e",
+            Self::H => "h",
+            Self::L => "l",
+            Self::IndHL => "[hl]",
+            Self::A => "a",
+        };
+
+        write!(f, "{}", out)
+    }
+}
+
+impl Display for CpuFlag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let out = match self {
+            Self::NZ => "nz",
+            Self::Z => "z",
+            Self::NC => "nc",
+            Self::C => "c",
+        };
+
+        write!(f, "{}", out)
+    }
+}
+
+impl Display for RegisterPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let out = match self {
+            Self::BC => "bc",
+            Self::DE => "de",
+            Self::HL => "hl",
+            Self::SP => "sp",
+        };
+
+        write!(f, "{}", out)
+    }
+}
+
+impl Display for StackPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let out = match self {
+            Self::BC => "bc",
+            Self::DE => "de",
+            Self::HL => "hl",
+            Self::AF => "af",
+        };
+
+        write!(f, "{}", out)
+    }
+}
+
+impl Display for IndirectPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let out = match self {
+            Self::BC => "[bc]",
+            Self::DE => "[de]",
+            Self::HLInc => "[hl+]",
+            Self::HLDec => "[hl-]",
+        };
+
+        write!(f, "{}", out)
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 use crate::codegen::{meta_instr::MetaInstructionTrait, Id};
 
 use super::{CpuFlag, GpRegister, IndirectPair, RegisterPair, StackPair};
@@ -36,6 +38,15 @@ pub enum Instruction<Meta>
     Jr(Condition, i8),
     LdR8FromR8(GpRegister, GpRegister),
     Cp(GpRegister),
+    CpImm(u8),
+    Add(GpRegister),
+    AddImm(u8),
+    And(GpRegister),
+    AndImm(u8),
+    Xor(GpRegister),
+    Or(GpRegister),
+    OrImm(u8),
+    Cpl,
     Pop(StackPair),
     Jp(Condition, u16),
     Push(StackPair),
@@ -46,6 +57,16 @@ pub enum Instruction<Meta>
     LdhToA(u8),
     LdhToAWithC,
     LdAFromInd(u16),
+    Call(Condition, u16),
+    Ret(Condition),
+    JpHl,
+    Reti,
+    Ei,
+    Di,
+    Halt,
+    /// Followed by a padding byte, which the CPU skips
+    Stop,
+    Nop,
     /// pretend this is an actual instruction (won't be emitted into the rom)
     Label(Id),
     Meta(Meta),
@@ -56,6 +77,7 @@ pub enum PrefixInstruction {
     Bit(Bit, GpRegister),
     Res(Bit, GpRegister),
     Set(Bit, GpRegister),
+    Swap(GpRegister),
 }
 
 impl<Meta> Instruction<Meta>
@@ -77,6 +99,15 @@ impl<Meta> Instruction<Meta>
             Jr(_, _) => 2,
             LdR8FromR8(_, _) => 1,
             Cp(_) => 1,
+            CpImm(_) => 2,
+            Add(_) => 1,
+            AddImm(_) => 2,
+            And(_) => 1,
+            AndImm(_) => 2,
+            Xor(_) => 1,
+            Or(_) => 1,
+            OrImm(_) => 2,
+            Cpl => 1,
             Pop(_) => 1,
             Jp(_, _) => 3,
             Push(_) => 1,
@@ -87,13 +118,24 @@ impl<Meta> Instruction<Meta>
             LdhToA(_) => 2,
             LdhToAWithC => 1,
             LdAFromInd(_) => 3,
+            Call(_, _) => 3,
+            Ret(_) => 1,
+            JpHl => 1,
+            Reti => 1,
+            Ei => 1,
+            Di => 1,
+            Halt => 1,
+            Stop => 2,
+            Nop => 1,
             Label(_) => 0,
+            Meta(meta) if meta.is_marker() => 0,
             Meta(_) => todo!(),
         }
     }
 
     fn base(&self) -> u8 {
         match self {
+            Self::Nop => 0x00,
             Self::LdR16Imm(_, _) => 0x01,
             Self::LdAToR16(_) => 0x02,
             Self::IncR16(_) => 0x03,
@@ -102,21 +144,40 @@ impl<Meta> Instruction<Meta>
             Self::LdR8Imm(_, _) => 0x06,
             Self::LdAFromR16(_) => 0x0a,
             Self::DecR16(_) => 0x0b,
+            Self::Cpl => 0x2f,
             Self::Jr(Condition::Always, _) => 0x18,
             Self::Jr(Condition::Flag(_), _) => 0x20,
+            Self::Halt => 0x76,
+            Self::Stop => 0x10,
             Self::LdR8FromR8(_, _) => 0x40,
+            Self::Add(_) => 0x80,
+            Self::And(_) => 0xa0,
+            Self::Xor(_) => 0xa8,
+            Self::Or(_) => 0xb0,
             Self::Cp(_) => 0xb8,
+            Self::Ret(Condition::Flag(_)) => 0xc0,
             Self::Pop(_) => 0xc1,
             Self::Jp(Condition::Flag(_), _) => 0xc2,
             Self::Jp(Condition::Always, _) => 0xc3,
+            Self::Call(Condition::Flag(_), _) => 0xc4,
             Self::Push(_) => 0xc5,
+            Self::AddImm(_) => 0xc6,
+            Self::Ret(Condition::Always) => 0xc9,
             Self::Prefixed(_) => 0xcb,
+            Self::Call(Condition::Always, _) => 0xcd,
+            Self::Reti => 0xd9,
             Self::LdhFromA(_) => 0xe0,
             Self::LdhFromAWithC => 0xe2,
+            Self::AndImm(_) => 0xe6,
+            Self::JpHl => 0xe9,
             Self::LdAToInd(_) => 0xea,
             Self::LdhToA(_) => 0xf0,
             Self::LdhToAWithC => 0xf2,
+            Self::Di => 0xf3,
+            Self::OrImm(_) => 0xf6,
             Self::LdAFromInd(_) => 0xfa,
+            Self::Ei => 0xfb,
+            Self::CpImm(_) => 0xfe,
             Self::Label(_) => 0xd3, // illegal opcode since these shouldnt be emitted
             Self::Meta(_) => 0xe3, // another illegal opcode since these shouldnt be directly emitted
         }
@@ -128,6 +189,7 @@ impl PrefixInstruction {
         match self {
             Self::Bit(_, _) => 0x40,
             Self::Res(_, _) => 0x80,
+            Self::Swap(_) => 0x30,
             Self::Set(_, _) => 0xc0,
         }
     }
@@ -137,6 +199,13 @@ impl<Meta> From<Instruction<Meta>> for Vec<u8>
         where Meta: Clone + std::fmt::Debug + MetaInstructionTrait {
     fn from(value: Instruction<Meta>) -> Self {
         use Instruction::*;
+
+        match value {
+            Label(_) => return Vec::new(),
+            Meta(ref meta) if meta.is_marker() => return Vec::new(),
+            _ => {}
+        }
+
         let mut out: Vec<u8> = Vec::with_capacity(3);
         out.push(value.base());
 
@@ -154,7 +223,15 @@ impl<Meta> From<Instruction<Meta>> for Vec<u8>
                 out.push(imm);
             }
             LdhFromA(imm)
-            | LdhToA(imm) => out.push(imm),
+            | LdhToA(imm)
+            | CpImm(imm)
+            | AddImm(imm)
+            | AndImm(imm)
+            | OrImm(imm) => out.push(imm),
+            Add(r8)
+            | And(r8)
+            | Xor(r8)
+            | Or(r8) => out[0] += r8 as u8,
             LdAFromInd(imm)
             | LdAToInd(imm) => out.extend(imm.to_le_bytes()),
             IncR8(r8)
@@ -163,24 +240,36 @@ impl<Meta> From<Instruction<Meta>> for Vec<u8>
             IncR16(r16)
             | DecR16(r16) => out[0] += r16 as u8 * 0x10,
             ref v @ Jp(condition, _)
-            | ref v @ Jr(condition, _) => {
+            | ref v @ Jr(condition, _)
+            | ref v @ Call(condition, _)
+            | ref v @ Ret(condition) => {
                 out[0] += match condition {
                     Condition::Always => 0,
                     Condition::Flag(flag) => flag as u8 * 0x08,
                 };
 
                 match v {
-                    Jp(_, imm) => out.extend(imm.to_le_bytes()),
+                    Jp(_, imm)
+                    | Call(_, imm) => out.extend(imm.to_le_bytes()),
                     Jr(_, imm) => out.push(*imm as u8),
-                    _ => unreachable!("Filtered down to just Jp|Jr in the outer match")
+                    Ret(_) => {},
+                    _ => unreachable!("Filtered down to just Jp|Jr|Call|Ret in the outer match")
                 }
             },
             Push(r16)
             | Pop(r16) => out[0] += r16 as u8 * 0x10,
             Prefixed(instruction) => out.push(instruction.into()),
             LdhFromAWithC
-            | LdhToAWithC => {},
-            Label(_) => {},
+            | LdhToAWithC
+            | JpHl
+            | Reti
+            | Ei
+            | Di
+            | Halt
+            | Nop
+            | Cpl => {},
+            Stop => out.push(0x00),
+            Label(_) => unreachable!("Labels aren't emitted"),
             Meta(_) => unimplemented!("Metainstruction unevaluated"),
             // e => unimplemented!("There is no {:?}", e)
         };
@@ -200,6 +289,7 @@ impl From<PrefixInstruction> for u8 {
                 let bit_offset = bit as u8 * 0x08;
                 base + reg_offset + bit_offset
             },
+            Pre::Swap(reg) => value.base() + reg as u8,
         }
     }
 }
@@ -209,4 +299,64 @@ impl<Meta> From<PrefixInstruction> for Instruction<Meta>
     fn from(value: PrefixInstruction) -> Self {
         Self::Prefixed(value)
     }
-}
\ No newline at end of file
+}
+impl<Meta> Display for Instruction<Meta>
+        where Meta: Clone + std::fmt::Debug + MetaInstructionTrait {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Instruction::*;
+
+        // conditional control flow reads `jp nz, $1234`, unconditional just `jp $1234`
+        let cond = |condition: &Condition| match condition {
+            Condition::Always => String::new(),
+            Condition::Flag(flag) => format!("{}, ", flag),
+        };
+
+        match self {
+            LdR16Imm(r16, imm) => write!(f, "ld {}, ${:04x}", r16, imm),
+            LdAToR16(r16) => write!(f, "ld {}, a", r16),
+            IncR16(r16) => write!(f, "inc {}", r16),
+            IncR8(r8) => write!(f, "inc {}", r8),
+            DecR8(r8) => write!(f, "dec {}", r8),
+            LdR8Imm(r8, imm) => write!(f, "ld {}, ${:02x}", r8, imm),
+            LdAFromR16(r16) => write!(f, "ld a, {}", r16),
+            DecR16(r16) => write!(f, "dec {}", r16),
+            Jr(condition, offset) => write!(f, "jr {}{}", cond(condition), offset),
+            LdR8FromR8(to, from) => write!(f, "ld {}, {}", to, from),
+            Cp(r8) => write!(f, "cp a, {}", r8),
+            CpImm(imm) => write!(f, "cp a, ${:02x}", imm),
+            Add(r8) => write!(f, "add a, {}", r8),
+            AddImm(imm) => write!(f, "add a, ${:02x}", imm),
+            And(r8) => write!(f, "and a, {}", r8),
+            AndImm(imm) => write!(f, "and a, ${:02x}", imm),
+            Xor(r8) => write!(f, "xor a, {}", r8),
+            Or(r8) => write!(f, "or a, {}", r8),
+            OrImm(imm) => write!(f, "or a, ${:02x}", imm),
+            Cpl => write!(f, "cpl"),
+            Pop(r16) => write!(f, "pop {}", r16),
+            Jp(condition, addr) => write!(f, "jp {}${:04x}", cond(condition), addr),
+            Push(r16) => write!(f, "push {}", r16),
+            Prefixed(PrefixInstruction::Bit(bit, r8)) => write!(f, "bit {}, {}", *bit as u8, r8),
+            Prefixed(PrefixInstruction::Res(bit, r8)) => write!(f, "res {}, {}", *bit as u8, r8),
+            Prefixed(PrefixInstruction::Set(bit, r8)) => write!(f, "set {}, {}", *bit as u8, r8),
+            Prefixed(PrefixInstruction::Swap(r8)) => write!(f, "swap {}", r8),
+            LdhFromA(imm) => write!(f, "ldh [$ff{:02x}], a", imm),
+            LdhFromAWithC => write!(f, "ldh [c], a"),
+            LdAToInd(addr) => write!(f, "ld [${:04x}], a", addr),
+            LdhToA(imm) => write!(f, "ldh a, [$ff{:02x}]", imm),
+            LdhToAWithC => write!(f, "ldh a, [c]"),
+            LdAFromInd(addr) => write!(f, "ld a, [${:04x}]", addr),
+            Call(condition, addr) => write!(f, "call {}${:04x}", cond(condition), addr),
+            Ret(Condition::Always) => write!(f, "ret"),
+            Ret(Condition::Flag(flag)) => write!(f, "ret {}", flag),
+            JpHl => write!(f, "jp hl"),
+            Reti => write!(f, "reti"),
+            Ei => write!(f, "ei"),
+            Di => write!(f, "di"),
+            Halt => write!(f, "halt"),
+            Stop => write!(f, "stop"),
+            Nop => write!(f, "nop"),
+            Label(id) => write!(f, "label_{}:", id),
+            Meta(meta) => write!(f, "; {:?}", meta),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use bitflags::bitflags;
+
+use crate::memory::Addr;
+
+bitflags! {
+    /// Bits of the IE and IF registers
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+    pub struct Interrupts: u8 {
+        const VBLANK    = 0b00000001;
+        const STAT      = 0b00000010;
+        const TIMER     = 0b00000100;
+        const SERIAL    = 0b00001000;
+        const JOYPAD    = 0b00010000;
+    }
+}
+
+/// Interrupt sources, in priority order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interrupt {
+    VBlank,
+    Stat,
+    Timer,
+    Serial,
+    Joypad,
+}
+
+impl Interrupt {
+    pub const ALL: [Interrupt; 5] = [Self::VBlank, Self::Stat, Self::Timer, Self::Serial, Self::Joypad];
+
+    /// Address the CPU calls when this interrupt is serviced
+    pub fn vector(&self) -> Addr {
+        0x40 + *self as Addr * 0x08
+    }
+
+    pub fn flag(&self) -> Interrupts {
+        Interrupts::from_bits_truncate(1 << *self as u8)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::VBlank => "vblank",
+            Self::Stat => "stat",
+            Self::Timer => "timer",
+            Self::Serial => "serial",
+            Self::Joypad => "joypad",
+        }
+    }
+}
+
+impl From<Interrupt> for Interrupts {
+    fn from(value: Interrupt) -> Self {
+        value.flag()
+    }
+}
@@ -0,0 +1,70 @@
+use bitflags::bitflags;
+
+use crate::codegen::variables::MemoryVariable;
+use crate::codegen::{Id, LoopCondition, Variable};
+use crate::memory::Addr;
+
+bitflags! {
+    /// Buttons as laid out in the joypad variables, a set bit means the button is down
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+    pub struct Buttons: u8 {
+        const A         = 0b00000001;
+        const B         = 0b00000010;
+        const SELECT    = 0b00000100;
+        const START     = 0b00001000;
+        const RIGHT     = 0b00010000;
+        const LEFT      = 0b00100000;
+        const UP        = 0b01000000;
+        const DOWN      = 0b10000000;
+    }
+}
+
+/// Writing this to P1 selects the action buttons
+pub const SELECT_BUTTONS: u8 = 0x10;
+/// Writing this to P1 selects the d-pad
+pub const SELECT_DPAD: u8 = 0x20;
+/// Writing this to P1 deselects both, so the lines stop drawing power
+pub const SELECT_NONE: u8 = 0x30;
+
+/// Which joypad variable to look at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtonEdge {
+    /// Down as of the last poll
+    Held,
+    /// Went down since the poll before
+    Pressed,
+    /// Went up since the poll before
+    Released,
+}
+
+/// WRAM bytes updated by [MacroAssembler::poll_joypad](crate::codegen::MacroAssembler::poll_joypad), one bit per [Buttons]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Joypad {
+    pub current: Addr,
+    pub pressed: Addr,
+    pub released: Addr,
+}
+
+impl Joypad {
+    pub fn addr(&self, edge: ButtonEdge) -> Addr {
+        match edge {
+            ButtonEdge::Held => self.current,
+            ButtonEdge::Pressed => self.pressed,
+            ButtonEdge::Released => self.released,
+        }
+    }
+
+    pub fn var(&self, edge: ButtonEdge) -> Variable {
+        Variable::Memory(MemoryVariable { addr: self.addr(edge), len: 1, id: Id::Unset })
+    }
+
+    /// Loops while any of `buttons` is in `edge`
+    pub fn while_any(&self, edge: ButtonEdge, buttons: Buttons) -> LoopCondition {
+        LoopCondition::Bits { addr: self.addr(edge), mask: buttons.bits(), while_set: true }
+    }
+
+    /// Loops until any of `buttons` is in `edge`, like waiting for A to be pressed
+    pub fn until_any(&self, edge: ButtonEdge, buttons: Buttons) -> LoopCondition {
+        LoopCondition::Bits { addr: self.addr(edge), mask: buttons.bits(), while_set: false }
+    }
+}
@@ -2,8 +2,14 @@
 
 pub mod codegen;
 
+pub mod apu;
+pub mod cartridge;
 pub mod cpu;
+pub mod joypad;
 pub mod memory;
+pub mod model;
 pub mod ppu;
+pub mod timer;
 
-pub use codegen::cgb::Cgb;
\ No newline at end of file
+pub use codegen::cgb::Cgb;
+pub use codegen::dmg::Dmg;
\ No newline at end of file
@@ -31,7 +31,7 @@ fn main() {
     assert_eq!(sys.allocator().borrow().registers.borrow().clone(), regs);
     dbg!(&sys.allocator().borrow().registers);
 
-    sys.disable_lcd_now();
+    sys.disable_lcd();
     assert_eq!(sys.allocator().borrow().registers.borrow().clone(), regs);
     dbg!(&sys.allocator().borrow().registers);
 
@@ -77,14 +77,23 @@ fn main() {
     sys.enable_lcd_now();
     sys.jr(Condition::Always, -2);
 
+    sys.header().title("GLEEBY");
+
+    let rom = sys.build().unwrap();
+
     let mut file = File::create("out.gb").unwrap();
+    rom.write_to(&mut file).unwrap();
 
-    sys.save(&mut file).unwrap();
+    let mut file = File::create("out.sym").unwrap();
+    rom.write_sym(&mut file).unwrap();
+
+    let mut file = File::create("out.lst").unwrap();
+    rom.write_listing(&mut file).unwrap();
 }
 
 #[cfg(test)]
 mod tests {
-    use gleeby::ppu::{palettes::PaletteColor, tiles::{Tile, TileRow}};
+    use gleeby::{apu::{Channel, Duty, Envelope, Noise, Pulse, SfxEvent, SoundEffect, Sweep}, cartridge::{header::{global_checksum, NINTENDO_LOGO}, HeaderBuilder, HeaderError, Mbc}, codegen::{allocator::ConstAllocError, assembler::BlockAssembler, block::EmitterError, BasicBlock, BuildError, LoopCondition, meta_instr::MetaInstruction, meta_instr::VarOrConst, state::{LcdState, Model, Palettes}, variables::{Constant, Variabler}, Assembler, AssemblerError, Id, MacroAssembler}, cpu::{interrupts::{Interrupt, Interrupts}, Condition, CpuSpeed, GpRegister}, joypad::{ButtonEdge, Buttons}, Dmg, memory::IoReg, timer::{TimerClock, TimerConfig}, ppu::{fade::{lerp, FadeTarget, PaletteFade}, import::{image::{Image, ImageError, TileError, TileErrorKind}, palette::{self, PaletteError}}, lcd::{LcdcFlags, StatFlags}, ConversionError, objects::{pos2, Metasprite, ObjAttributeFlags, ObjAttributes, ObjSize, Sprite, SpriteIdx}, palettes::{CgbPalette, Color, ColorCorrection, DmgPalette, DmgPaletteKind, PaletteColor, PaletteKind, Shade}, tiles::{AttributeMap, Tile, TileAttributeFlags, TileAttributes, TileRow, Tilemap}, TiledataSelector, TilemapSelector, VramBank}, Cgb};
 
     #[test]
     fn tile_from_u8() {
@@ -163,4 +172,867 @@ mod tests {
 
         assert_eq!(tile.as_bytes(), target_bytes);
     }
+
+    #[test]
+    fn rom_banking() {
+        let mut sys = Cgb::with_mbc(Mbc::Mbc5);
+        let bank = sys.new_section().unwrap();
+        let entry = {
+            let section = sys.section(bank).unwrap();
+            section.inner.ld_r8_imm(GpRegister::B, 0x42).ret(Condition::Always);
+            assert_eq!(section.inner.push_rom_bank(bank + 1), Err(AssemblerError::RomBankFromRomx(bank)));
+            assert_eq!(section.inner.basic_block().switch_rom_bank(0), Err(AssemblerError::RomBankFromRomx(bank)));
+            section.entry()
+        };
+        assert_eq!((entry.bank, entry.addr), (1, 0x4000));
+
+        assert_eq!(sys.push_rom_bank(0x100), Err(AssemblerError::ArgumentError));
+        sys.push_rom_bank(2).unwrap();
+        sys.pop_rom_bank(2);
+        sys.far_call(entry.bank, entry.addr).unwrap();
+        sys.far_jp(entry.bank, entry.addr).unwrap();
+
+        let reg_a = sys.claim_reg(GpRegister::A, Id::Unset);
+        assert_eq!(sys.far_jp(entry.bank, entry.addr), Err(AssemblerError::FarJumpClobbers));
+        sys.far_call(entry.bank, entry.addr).unwrap();
+        drop(reg_a);
+
+        // ROM0 constants are full after this, so the next one goes in a ROMX bank
+        sys.new_stored_const(&[0; 0x2000]).unwrap();
+        let banked = sys.new_stored_const(&[0x12, 0x34, 0x56]).unwrap();
+        assert_ne!(banked.bank, 0);
+
+        // so is a palette's, which ROMX code can't switch to, and the failed write isn't tracked
+        let section = sys.section(bank).unwrap();
+        assert_eq!(section.inner.set_palette(CgbPalette::_3, [Color::RED; 4]), Err(AssemblerError::RomBankFromRomx(bank)));
+        assert_eq!(sys.allocator().borrow().state.palettes.get(PaletteKind::Background, CgbPalette::_3), None);
+
+        let rom = sys.build().unwrap();
+        let push_pop = [
+            0xf5, 0xf0, 0x80, 0xf5, 0x3e, 0x02, 0xe0, 0x80, 0xea, 0x00, 0x20,
+            0xf1, 0xe0, 0x80, 0xea, 0x00, 0x20, 0xf1,
+        ];
+        let far_call = [0x21, 0x00, 0x40, 0x3e, 0x01, 0xcd, 0x68, 0x00];
+        let far_jp = [0x21, 0x00, 0x40, 0x3e, 0x01, 0xc3, 0x81, 0x00];
+        let code: Vec<u8> = [&push_pop[..], &far_call, &far_jp, &[0xf5], &far_call, &[0xf1]].concat();
+        assert!(rom.bytes.windows(code.len()).any(|window| window == code));
+
+        assert_eq!(rom.bytes[0x4000..0x4003], [0x06, 0x42, 0xc9]);
+        let offset = banked.bank as usize * 0x4000 + (banked.addr - 0x4000) as usize;
+        assert_eq!(rom.bytes[offset..offset + 3], [0x12, 0x34, 0x56]);
+    }
+
+    #[test]
+    fn cartridge_ram() {
+        assert_eq!(Cgb::new().new_sram_var(1), Err(AssemblerError::AllocError(ConstAllocError::NoCartridgeRam)));
+
+        let mut sys = Cgb::with_mbc(Mbc::Mbc1);
+        let first = sys.new_sram_var(0x1000).unwrap();
+        let second = sys.new_sram_var(0x1000).unwrap();
+        let spilled = sys.new_sram_var(1).unwrap();
+        assert_eq!((first.bank, second.bank, spilled.bank), (0, 0, 1));
+        for _ in 0..2 {
+            sys.new_sram_var(0x2000).unwrap();
+        }
+        assert_eq!(sys.new_sram_var(1), Err(AssemblerError::AllocError(ConstAllocError::OutOfBanks)));
+
+        sys.with_sram(&spilled, |sram| {
+            sram.ld_a_from(0)?;
+            Ok(())
+        }).unwrap();
+        let past_end = sys.with_sram(&spilled, |sram| {
+            sram.ld_a_to(1)?;
+            Ok(())
+        });
+        assert_eq!(past_end, Err(AssemblerError::ArgumentError));
+
+        // enable, MBC1 RAM banking mode, bank 1, the access, then disable. Failing closures still disable it
+        let rom = sys.build().unwrap();
+        let enable = [
+            0x3e, 0x0a, 0xea, 0x00, 0x00,
+            0x3e, 0x01, 0xea, 0x00, 0x60,
+            0x3e, 0x01, 0xea, 0x00, 0x40,
+        ];
+        let disable = [0x3e, 0x00, 0xea, 0x00, 0x00];
+        let window = [&enable[..], &[0xfa, 0x00, 0xa0], &disable, &enable, &disable].concat();
+        assert!(rom.bytes.windows(window.len()).any(|bytes| bytes == window));
+    }
+
+    #[test]
+    fn header_checksums() {
+        let mut rom = vec![0; 0x8000];
+        let header = HeaderBuilder::default().title("GLEEBY").build(Mbc::RomOnly, 2, 0).unwrap();
+        header.write_to(&mut rom);
+
+        assert_eq!(&rom[0x104..0x134], &NINTENDO_LOGO);
+        assert_eq!(&rom[0x134..0x13a], b"GLEEBY");
+        assert_eq!(rom[0x14d], 0xae);
+        assert_eq!(u16::from_be_bytes([rom[0x14e], rom[0x14f]]), global_checksum(&rom));
+    }
+
+    #[test]
+    fn header_title_too_long() {
+        let header = HeaderBuilder::default()
+            .title("GLEEBYGLEEBY")
+            .manufacturer(*b"GLBY")
+            .build(Mbc::RomOnly, 2, 0);
+
+        assert_eq!(header, Err(HeaderError::TitleTooLong { max: 11, len: 12 }));
+    }
+
+    #[test]
+    fn header_rom_size_override() {
+        assert_eq!(HeaderBuilder::default().rom_size(0x09).build(Mbc::RomOnly, 2, 0), Err(HeaderError::InvalidRomSize(0x09)));
+
+        let mut sys = Cgb::new();
+        sys.header().rom_size(0x30);
+        assert_eq!(sys.build().unwrap_err(), BuildError::HeaderError(HeaderError::InvalidRomSize(0x30)));
+
+        let mut sys = Cgb::new();
+        sys.header().rom_size(0x01);
+        assert_eq!(sys.build().unwrap().bytes.len(), 0x10000);
+    }
+
+    #[test]
+    fn rom_image_in_memory() {
+        let mut sys = Cgb::new();
+        sys.jr(Condition::Always, -2);
+        sys.fill_byte(0xaa);
+
+        let rom = sys.build().unwrap();
+
+        assert_eq!(rom.len(), 0x8000);
+        assert_eq!(&rom.bytes[0x100..0x103], &[0xc3, 0x50, 0x01]);
+        assert_eq!(rom.bytes[0x7fff], 0xaa);
+
+        let mut out = Vec::new();
+        rom.write_to(&mut out).unwrap();
+        assert_eq!(out, rom.bytes);
+
+        // building doesn't consume the system, so it can be extended and built again
+        assert_eq!(sys.build().unwrap(), rom);
+        let stored = sys.new_stored_const(&[0x12, 0x34]).unwrap();
+        sys.nop();
+        let rebuilt = sys.build().unwrap();
+        assert_eq!(&rebuilt.bytes[0x153..0x156], &[0x18, 0xfe, 0x00]);
+        assert_eq!(&rebuilt.bytes[stored.addr as usize..stored.addr as usize + 2], &[0x12, 0x34]);
+        assert_eq!(sys.build().unwrap(), rebuilt);
+    }
+
+    #[test]
+    fn symbol_file() {
+        let mut sys = Cgb::new();
+        let var = sys.new_hram_var(1).unwrap();
+        sys.name_var(&var, "frame counter").unwrap();
+        sys.basic_block().label(Id::Set(0)).jr(Condition::Always, -2);
+
+        let rom = sys.build().unwrap();
+        let mut out = Vec::new();
+        rom.write_sym(&mut out).unwrap();
+        let sym = String::from_utf8(out).unwrap();
+
+        assert!(sym.contains("00:0100 entry\n"));
+        assert!(sym.contains("00:0150 main\n"));
+        assert!(sym.contains("00:0153 main.basic_block_1.label_0\n"));
+        assert!(sym.contains("00:ff81 frame_counter\n"));
+
+        let mut block = BasicBlock::<MetaInstruction>::new(Cgb::new().allocator());
+        block.nop().loop_block(LoopCondition::Native(Condition::Always));
+        assert_eq!(block.flatten().err(), Some(AssemblerError::EmitterError(EmitterError::LoopNotFlattenable)));
+    }
+
+    #[test]
+    fn listing() {
+        let mut sys = Cgb::new();
+        // failing macros mustn't leave an open marker behind
+        let wide = sys.new_hram_var(2).unwrap();
+        assert!(sys.enable_sram(0).is_err());
+        assert!(sys.add_to_ioreg(IoReg::Scx, &wide).is_err());
+        sys.store_byte(0xc000, 0x12).unwrap();
+        sys.basic_block().label(Id::Set(0)).jr(Condition::Always, -2);
+
+        let rom = sys.build().unwrap();
+        let mut out = Vec::new();
+        rom.write_listing(&mut out).unwrap();
+        let listing = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = listing.lines().collect();
+
+        assert_eq!(lines[0], "00:0150  31 00 e0  ld sp, $e000             main");
+        assert_eq!(lines[1], "00:0153  3e 12     ld a, $12                main  ; store_byte");
+        assert_eq!(lines[2], "00:0155  ea 00 c0  ld [$c000], a            main  ; store_byte");
+        assert_eq!(lines[3], "00:0158            label_0:                 main > basic_block#1");
+        assert_eq!(lines[4], "00:0158  18 fe     jr -2                    main > basic_block#1");
+        assert_eq!(&rom.bytes[0x158..0x15a], &[0x18, 0xfe]);
+    }
+
+    #[test]
+    fn interrupt_handlers() {
+        let mut sys = Cgb::new();
+        sys.set_interrupt_enable(Interrupts::VBLANK).unwrap();
+        sys.ei();
+        sys.interrupt(Interrupt::VBlank, |handler| handler.store_byte(0xc000, 0x12)).unwrap();
+
+        let rom = sys.build().unwrap();
+
+        // ld sp, $e000; ld a, $01; ldh [$ffff], a; ei
+        let handler = 0x150 + 3 + 2 + 2 + 1;
+        assert_eq!(&rom.bytes[0x40..0x43], &[0xc3, handler as u8, (handler >> 8) as u8]);
+        assert_eq!(rom.bytes[0x48], 0xd9);
+        assert_eq!(&rom.bytes[handler..handler + 4], &[0xf5, 0xc5, 0xd5, 0xe5]);
+        assert_eq!(&rom.bytes[handler + 9..handler + 14], &[0xe1, 0xd1, 0xc1, 0xf1, 0xd9]);
+    }
+
+    #[test]
+    fn lcd_disable_safety() {
+        let mut sys = Cgb::new();
+        assert_eq!(sys.disable_lcd_now(), Err(AssemblerError::UnsafeLcdDisable(LcdState::Unknown)));
+
+        sys.assume_vblank();
+        assert_eq!(sys.disable_lcd_now(), Ok(()));
+
+        sys.enable_lcd_now();
+        assert_eq!(sys.disable_lcd_now(), Err(AssemblerError::UnsafeLcdDisable(LcdState::On)));
+
+        // assumptions made in a handler stay there
+        sys.interrupt(Interrupt::VBlank, |handler| {
+            handler.assume_vblank();
+            handler.disable_lcd_now()
+        }).unwrap();
+        assert_eq!(sys.disable_lcd_now(), Err(AssemblerError::UnsafeLcdDisable(LcdState::On)));
+
+        // the second one knows the LCD is off and doesn't wait
+        sys.disable_lcd();
+        sys.disable_lcd();
+        let rom = sys.build().unwrap();
+        let lcd_off = [0xf0, 0x40, 0xcb, 0xbf, 0xe0, 0x40];
+        let lcd_on = [0xf0, 0x40, 0xcb, 0xff, 0xe0, 0x40];
+
+        assert_eq!(&rom.bytes[0x153..0x159], &lcd_off);
+        assert_eq!(&rom.bytes[0x159..0x15f], &lcd_on);
+        assert_eq!(&rom.bytes[0x15f..0x16b], &[0xf0, 0x40, 0xcb, 0x7f, 0x28, 0x06, 0xf0, 0x44, 0xfe, 0x90, 0x38, 0xfa]);
+        assert_eq!(&rom.bytes[0x16b..0x171], &lcd_off);
+        assert_eq!(&rom.bytes[0x171..0x177], &lcd_off);
+    }
+
+    #[test]
+    fn shadow_oam() {
+        let mut sys = Cgb::new();
+        let sprite = Sprite { pos: pos2(16, 24), tile: 3, attr: Default::default() };
+        assert_eq!(sys.set_sprite(sprite, SpriteIdx::_1), Err(AssemblerError::OamDmaUninitialized));
+
+        sys.init_oam_dma().unwrap();
+        sys.set_sprite(sprite, SpriteIdx::_1).unwrap();
+        sys.schedule_oam_dma();
+
+        let oam_dma = sys.allocator().borrow().state.oam_dma.unwrap();
+        assert_eq!(oam_dma.shadow, 0xc000);
+        assert_eq!(oam_dma.routine, 0xff81);
+
+        let rom = sys.build().unwrap();
+        assert_eq!(&rom.bytes[0x2000..0x2008], &[0xe0, 0x46, 0x3e, 0x28, 0x3d, 0x20, 0xfd, 0xc9]);
+
+        let handler = u16::from_le_bytes([rom.bytes[0x41], rom.bytes[0x42]]) as usize;
+        assert_eq!(&rom.bytes[handler + 4..handler + 9], &[0x3e, 0xc0, 0xcd, 0x81, 0xff]);
+    }
+
+    #[test]
+    fn metasprite_flip() {
+        let mut metasprite = Metasprite::default();
+        metasprite.entry(0, 0, 1, Default::default())
+            .entry(8, 0, 2, Default::default())
+            .entry(0, 8, 3, ObjAttributes { flags: ObjAttributeFlags::X_FLIP, ..Default::default() });
+
+        let flipped = metasprite.flipped(ObjAttributeFlags::X_FLIP | ObjAttributeFlags::BG_PRIORITY, ObjSize::_8x8);
+        let offsets: Vec<(i8, i8)> = flipped.entries.iter().map(|entry| (entry.dx, entry.dy)).collect();
+
+        assert_eq!(offsets, [(-8, 0), (-16, 0), (-8, 8)]);
+        assert_eq!(flipped.entries[0].attr.flags, ObjAttributeFlags::X_FLIP);
+        assert_eq!(flipped.entries[2].attr.flags, ObjAttributeFlags::empty());
+
+        let flipped = metasprite.flipped(ObjAttributeFlags::Y_FLIP, ObjSize::_8x16);
+        let offsets: Vec<(i8, i8)> = flipped.entries.iter().map(|entry| (entry.dx, entry.dy)).collect();
+        assert_eq!(offsets, [(0, -16), (8, -16), (0, -24)]);
+    }
+
+    #[test]
+    fn metasprite_placement() {
+        let mut sys = Cgb::new();
+        sys.init_oam_dma().unwrap();
+        let x = sys.new_hram_var(1).unwrap();
+        let y = sys.new_hram_var(1).unwrap();
+
+        let mut metasprite = Metasprite::default();
+        metasprite.entry(-4, 8, 5, Default::default());
+        sys.set_metasprite(&metasprite, SpriteIdx::_2, &x, &y, ObjAttributeFlags::empty()).unwrap();
+
+        let rom = sys.build().unwrap();
+        let placement = [
+            0xfa, 0x8a, 0xff, 0xc6, 0x08, 0xea, 0x08, 0xc0,
+            0xfa, 0x89, 0xff, 0xc6, 0xfc, 0xea, 0x09, 0xc0,
+            0x3e, 0x05, 0xea, 0x0a, 0xc0,
+        ];
+
+        assert!(rom.bytes.windows(placement.len()).any(|window| window == placement));
+    }
+
+    #[test]
+    fn joypad_wait_for_start() {
+        let mut sys = Cgb::new();
+        assert_eq!(sys.poll_joypad(), Err(AssemblerError::JoypadUninitialized));
+
+        let joypad = sys.init_joypad().unwrap();
+        assert_eq!(joypad.current, 0xc000);
+
+        sys.loop_block(joypad.until_any(ButtonEdge::Pressed, Buttons::START)).poll_joypad().unwrap();
+
+        let rom = sys.build().unwrap();
+        let lst = {
+            let mut out = Vec::new();
+            rom.write_listing(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert!(lst.contains("cb 37     swap a"));
+        // ld a, [pressed]; and a, START; jr z, <start of poll>
+        let footer = [0xfa, 0x01, 0xc0, 0xe6, 0x08, 0x28];
+        let at = rom.bytes.windows(footer.len()).position(|window| window == footer).unwrap();
+        let offset = rom.bytes[at + footer.len()] as i8;
+        assert_eq!(rom.bytes[(at as isize + 7 + offset as isize) as usize], 0xf5);
+
+        // the test result lives in the flags, so a held `a` can't be saved around it
+        let len = sys.len();
+        let reg_a = sys.claim_reg(GpRegister::A, Id::Unset);
+        assert_eq!(sys.test_buttons(ButtonEdge::Pressed, Buttons::A), Err(AssemblerError::RegisterInUse(GpRegister::A)));
+        assert_eq!(sys.len(), len);
+        drop(reg_a);
+        assert!(sys.test_buttons(ButtonEdge::Pressed, Buttons::A).is_ok());
+    }
+
+    #[test]
+    fn loop_too_big() {
+        let mut sys = Cgb::new();
+        let joypad = sys.init_joypad().unwrap();
+        let body = sys.loop_block(joypad.until_any(ButtonEdge::Pressed, Buttons::START));
+        for _ in 0..130 {
+            body.nop();
+        }
+
+        // body, ld a, [addr], and, jr
+        let loop_length = 130 + 3 + 2 + 2;
+        let errors = vec![AssemblerError::EmitterError(EmitterError::LoopTooBig(loop_length))];
+        assert_eq!(sys.build().err(), Some(BuildError::AssemblerErrors(errors)));
+    }
+
+    #[test]
+    fn sound_effect() {
+        let mut sfx = SoundEffect::new();
+        sfx.frame([SfxEvent::Pulse1(Pulse { duty: Duty::Half, length: Some(16), envelope: Envelope::fade_out(15, 2), period: 0x783 }, Sweep::default())])
+            .wait(2)
+            .frame([SfxEvent::Noise(Noise { envelope: Envelope::fixed(8), clock_shift: 5, ..Default::default() }), SfxEvent::Stop(Channel::Pulse1)]);
+
+        let bytes = sfx.to_bytes();
+        assert_eq!(bytes, [
+            5, 0x10, 0x00, 0x11, 0xb0, 0x12, 0xf2, 0x13, 0x83, 0x14, 0xc7,
+            0,
+            0,
+            5, 0x20, 0x00, 0x21, 0x80, 0x22, 0x50, 0x23, 0x80, 0x12, 0x00,
+            0xff,
+        ]);
+
+        let mut sys = Cgb::new();
+        assert_eq!(sys.play_sfx(&sfx), Err(AssemblerError::SoundUninitialized));
+
+        sys.init_sound().unwrap();
+        let mut too_loud = SoundEffect::new();
+        too_loud.frame([SfxEvent::Pulse2(Pulse { envelope: Envelope::fixed(16), ..Default::default() })]);
+        assert_eq!(sys.play_sfx(&too_loud), Err(AssemblerError::ArgumentError));
+
+        sys.play_sfx(&sfx).unwrap();
+        sys.schedule_sfx();
+
+        let rom = sys.build().unwrap();
+        assert!(rom.bytes.windows(bytes.len()).any(|window| window == bytes));
+        assert!(rom.symbols.iter().any(|symbol| symbol.name == "sfx_pointer"));
+
+        // every jump in the stepper lands on an instruction, and the skip lands on the pops
+        let stepper = [0xfa, 0x01, 0xc0, 0xb7, 0x28, 35];
+        let at = rom.bytes.windows(stepper.len()).position(|window| window == stepper).unwrap();
+        assert_eq!(rom.bytes[at + stepper.len() + 35..][..3], [0xe1, 0xc1, 0xf1]);
+        assert_eq!(rom.bytes[at + 27 - 7], 0x2a);
+
+        // with ROM0 full the effect can't go anywhere the stepper reaches, and nothing spills into ROMX
+        let mut sys = Cgb::new();
+        sys.init_sound().unwrap();
+        sys.new_stored_const(&[0; 0x2000]).unwrap();
+        assert_eq!(sys.play_sfx(&sfx), Err(AssemblerError::AllocError(ConstAllocError::OutOfMemory)));
+        assert_eq!(sys.allocator().borrow().bank_count(), 2);
+        assert!(!sys.build().unwrap().symbols.iter().any(|symbol| symbol.addr.bank != 0));
+    }
+
+    #[test]
+    fn timer_frequency() {
+        assert_eq!(TimerConfig::for_frequency(60), Some(TimerConfig { clock: TimerClock::Hz4096, modulo: 188 }));
+        assert_eq!(TimerConfig::for_frequency(16384), Some(TimerConfig { clock: TimerClock::Hz262144, modulo: 240 }));
+        assert_eq!(TimerConfig::for_frequency(1000).unwrap().frequency(), 992);
+        assert_eq!(TimerConfig::for_frequency(1024), Some(TimerConfig { clock: TimerClock::Hz262144, modulo: 0 }));
+        assert_eq!(TimerConfig::for_frequency(15), None);
+        assert_eq!(TimerConfig::for_frequency(0), None);
+
+        let mut sys = Cgb::new();
+        assert_eq!(sys.set_timer_frequency(8), Err(AssemblerError::ArgumentError));
+
+        let config = sys.set_timer_frequency(60).unwrap();
+        assert_eq!(config.frequency(), 60);
+
+        let rom = sys.build().unwrap();
+        // TAC stopped, TMA, TIMA, TAC running at 4096 Hz, then the Timer bit set in IE
+        let start = [0x3e, 0x00, 0xe0, 0x07, 0x3e, 0xbc, 0xe0, 0x06, 0x3e, 0xbc, 0xe0, 0x05, 0x3e, 0x04, 0xe0, 0x07];
+        let enable = [0xf5, 0xf0, 0xff, 0xf6, 0x04, 0xe0, 0xff, 0xf1];
+        assert!(rom.bytes.windows(start.len()).any(|window| window == start));
+        assert!(rom.bytes.windows(enable.len()).any(|window| window == enable));
+    }
+
+    #[test]
+    fn double_speed() {
+        let mut sys = Cgb::new();
+        sys.set_speed(CpuSpeed::Double).unwrap();
+        assert_eq!(sys.allocator().borrow().state.speed, CpuSpeed::Double);
+
+        // the timer counts twice as fast, so it needs twice the ticks
+        let config = sys.set_timer_frequency(60).unwrap();
+        assert_eq!(config, TimerConfig { clock: TimerClock::Hz4096, modulo: 119 });
+        assert_eq!(config.frequency_at(CpuSpeed::Double), 59);
+
+        let rom = sys.build().unwrap();
+        let switch = [
+            0xf5, 0xf0, 0x4d, 0xcb, 0x7f, 0x20, 0x13,
+            0xf0, 0xff, 0xf5, 0xaf, 0xe0, 0xff, 0x3e, 0x30, 0xe0, 0x00, 0x3e, 0x01, 0xe0, 0x4d, 0x10, 0x00,
+            0xf1, 0xe0, 0xff, 0xf1,
+        ];
+        assert!(rom.bytes.windows(switch.len()).any(|window| window == switch));
+    }
+
+    #[test]
+    fn bg_attributes() {
+        let attr = TileAttributes { flags: TileAttributeFlags::X_FLIP | TileAttributeFlags::BANK1, palette: CgbPalette::_3 };
+        assert_eq!(u8::from(attr), 0x2b);
+
+        let mut map = AttributeMap::default();
+        map.map[33] = attr;
+        assert_eq!(map[1][1], attr);
+
+        let mut sys = Cgb::new();
+        sys.write_tile_data_in_bank(VramBank::_1, TiledataSelector::Tiledata8000, 0, &Tile::flat(PaletteColor::_1)).unwrap();
+        sys.set_tile_attributes(TilemapSelector::Tilemap9800, 33, attr).unwrap();
+        assert_eq!(sys.allocator().borrow().state.vram_bank, VramBank::_1);
+        sys.set_tile(TilemapSelector::Tilemap9800, 0, 0).unwrap();
+        assert_eq!(sys.allocator().borrow().state.vram_bank, VramBank::_0);
+
+        let rom = sys.build().unwrap();
+        let lst = {
+            let mut out = Vec::new();
+            rom.write_listing(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        // bank 1 once for both the tile data and the attribute, then back to 0 for the tile index
+        assert_eq!(lst.matches("ldh [$ff4f], a").count(), 2);
+        assert!(rom.bytes.windows(4).any(|window| window == [0x3e, 0x01, 0xe0, 0x4f]));
+        assert!(rom.bytes.windows(4).any(|window| window == [0x3e, 0x00, 0xe0, 0x4f]));
+        assert!(rom.bytes.windows(5).any(|window| window == [0x3e, 0x2b, 0xea, 0x21, 0x98]));
+    }
+
+    #[test]
+    fn vram_dma() {
+        let mut sys = Cgb::new();
+        assert_eq!(sys.general_dma(0x4008, 0x8000, 0x10), Err(AssemblerError::ArgumentError));
+        assert_eq!(sys.general_dma(0x4000, 0x9ff0, 0x20), Err(AssemblerError::ArgumentError));
+        assert_eq!(sys.hblank_dma(0x8800, 0x8000, 0x10), Err(AssemblerError::ArgumentError));
+
+        sys.set_tilemap(TilemapSelector::Tilemap9800, Tilemap::from([1; 32 * 32])).unwrap();
+        sys.set_attribute_map(TilemapSelector::Tilemap9C00, &AttributeMap::default()).unwrap();
+        sys.hblank_dma(0xc000, 0x8800, 0x100).unwrap();
+        sys.wait_hblank_dma();
+
+        let rom = sys.build().unwrap();
+        let tilemap = rom.bytes.windows(0x400).position(|window| window == [1; 0x400]).unwrap();
+        assert_eq!(tilemap % 0x10, 0);
+
+        // source, destination at 0x1800 into VRAM, then 64 blocks in one go
+        let general = [
+            0x3e, (tilemap >> 8) as u8, 0xe0, 0x51, 0x3e, tilemap as u8, 0xe0, 0x52,
+            0x3e, 0x18, 0xe0, 0x53, 0x3e, 0x00, 0xe0, 0x54, 0x3e, 0x3f, 0xe0, 0x55,
+        ];
+        assert!(rom.bytes.windows(general.len()).any(|window| window == general));
+
+        let hblank = [
+            0x3e, 0xc0, 0xe0, 0x51, 0x3e, 0x00, 0xe0, 0x52,
+            0x3e, 0x08, 0xe0, 0x53, 0x3e, 0x00, 0xe0, 0x54, 0x3e, 0x8f, 0xe0, 0x55,
+            0xf0, 0x55, 0x3c, 0x20, 0xfb,
+        ];
+        assert!(rom.bytes.windows(hblank.len()).any(|window| window == hblank));
+        assert_eq!(rom.bytes.windows(2).filter(|window| *window == [0xe0, 0x55]).count(), 3);
+    }
+
+    #[test]
+    fn object_palettes() {
+        let colors = [Color::WHITE, Color::RED, Color::GREEN, Color::BLUE];
+        let mut sys = Cgb::new();
+        sys.set_palette(CgbPalette::_1, colors).unwrap();
+        sys.set_obj_palette(CgbPalette::_2, colors).unwrap();
+
+        let palettes = sys.allocator().borrow().state.palettes;
+        assert_eq!(palettes.get(PaletteKind::Background, CgbPalette::_1), Some(colors));
+        assert_eq!(palettes.get(PaletteKind::Object, CgbPalette::_2), Some(colors));
+        assert_eq!(palettes.get(PaletteKind::Object, CgbPalette::_1), None);
+
+        let rom = sys.build().unwrap();
+        let lst = {
+            let mut out = Vec::new();
+            rom.write_listing(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        // auto-incrementing from the first byte of each palette
+        assert!(rom.bytes.windows(4).any(|window| window == [0x3e, 0x88, 0xe0, 0x68]));
+        assert!(rom.bytes.windows(4).any(|window| window == [0x3e, 0x90, 0xe0, 0x6a]));
+        assert!(lst.contains("ldh [$ff6b], a"));
+    }
+
+    #[test]
+    fn dmg_target() {
+        let inverted = DmgPalette([Shade::Black, Shade::DarkGrey, Shade::LightGrey, Shade::White]);
+        assert_eq!(u8::from(DmgPalette::DEFAULT), 0xe4);
+        assert_eq!(u8::from(inverted), 0x1b);
+
+        let mut sys = Dmg::new();
+        assert_eq!(sys.set_palette(CgbPalette::_0, [Color::WHITE; 4]), Err(AssemblerError::CgbOnly));
+        assert_eq!(sys.set_vram_bank(VramBank::_1), Err(AssemblerError::CgbOnly));
+        assert_eq!(sys.set_speed(CpuSpeed::Double), Err(AssemblerError::CgbOnly));
+        assert_eq!(sys.general_dma(0xc000, 0x8000, 0x10), Err(AssemblerError::CgbOnly));
+
+        sys.set_dmg_palette(DmgPaletteKind::Background, DmgPalette::DEFAULT).unwrap();
+        sys.set_dmg_palette(DmgPaletteKind::Object1, inverted).unwrap();
+        assert_eq!(sys.allocator().borrow().state.palettes.get_dmg(DmgPaletteKind::Object1), Some(inverted));
+
+        // aligned, but DMG has no VRAM DMA so it's copied by the CPU
+        sys.write_tile_data(TiledataSelector::Tiledata8000, 0, &Tile::flat(PaletteColor::_2)).unwrap();
+
+        let rom = sys.build().unwrap();
+        assert_eq!(rom.bytes[0x143], 0x00);
+        assert!(rom.bytes.windows(4).any(|window| window == [0x3e, 0xe4, 0xe0, 0x47]));
+        assert!(rom.bytes.windows(4).any(|window| window == [0x3e, 0x1b, 0xe0, 0x49]));
+        assert!(!rom.bytes.windows(2).any(|window| window == [0xe0, 0x55]));
+        assert!(!rom.bytes.windows(2).any(|window| window == [0xe0, 0x68]));
+
+        let rom = Cgb::new().build().unwrap();
+        assert_eq!(rom.bytes[0x143], 0x80);
+    }
+
+    #[test]
+    fn ioreg_validation() {
+        assert_eq!(IoReg::try_from(0xff44), Ok(IoReg::Ly));
+        assert_eq!(IoReg::try_from(0xff03), Err(0xff03));
+        assert!(IoReg::ALL.iter().all(|reg| IoReg::try_from(reg.addr()) == Ok(*reg)));
+        assert!(IoReg::Ly.is_read_only());
+        assert_eq!(IoReg::Vbk.info().model, Some(Model::Cgb));
+        assert!(IoReg::Bgp.exists_on(Model::Cgb) && IoReg::Bgp.exists_on(Model::Dmg));
+
+        let mut sys = Cgb::new();
+        assert_eq!(sys.set_ioreg(IoReg::Ly, 0), Err(AssemblerError::ReadOnlyRegister(IoReg::Ly)));
+        assert_eq!(sys.store_byte(0xff44, 0), Err(AssemblerError::ReadOnlyRegister(IoReg::Ly)));
+        assert_eq!(sys.set_ioreg(IoReg::Stat, 0x03), Err(AssemblerError::UnwritableBits { reg: IoReg::Stat, value: 0x03 }));
+        sys.set_ioreg(IoReg::Stat, 0x40).unwrap();
+        sys.set_ioreg(IoReg::Vbk, 1).unwrap();
+        assert_eq!(sys.allocator().borrow().state.vram_bank, VramBank::_1);
+        sys.store_byte(0xff4f, 0).unwrap();
+        assert_eq!(sys.allocator().borrow().state.vram_bank, VramBank::_0);
+        sys.store_byte(0xff03, 0x12).unwrap();
+
+        let mut sys = Dmg::new();
+        assert_eq!(sys.set_ioreg(IoReg::Vbk, 1), Err(AssemblerError::UnavailableRegister(IoReg::Vbk, Model::Dmg)));
+        assert_eq!(sys.store_byte(0xff70, 1), Err(AssemblerError::UnavailableRegister(IoReg::Svbk, Model::Dmg)));
+        sys.set_ioreg(IoReg::Bgp, 0xe4).unwrap();
+        sys.build().unwrap();
+    }
+
+    #[test]
+    fn lcdc_stat() {
+        let flags = LcdcFlags::LCD_ENABLE | LcdcFlags::BG_ENABLE | LcdcFlags::tiledata(TiledataSelector::Tiledata8000)
+            | LcdcFlags::window_tilemap(TilemapSelector::Tilemap9C00) | LcdcFlags::obj_size(ObjSize::_8x16);
+        assert_eq!(flags.bits(), 0xd5);
+        assert_eq!(flags.get_bg_tilemap(), TilemapSelector::Tilemap9800);
+        assert_eq!(flags.get_window_tilemap(), TilemapSelector::Tilemap9C00);
+        assert_eq!(flags.get_obj_size(), ObjSize::_8x16);
+
+        let mut sys = Cgb::new();
+        assert_eq!(sys.set_lcdc(LcdcFlags::BG_ENABLE), Err(AssemblerError::UnsafeLcdDisable(LcdState::Unknown)));
+        assert_eq!(sys.modify_lcdc(LcdcFlags::empty(), LcdcFlags::LCD_ENABLE), Err(AssemblerError::UnsafeLcdDisable(LcdState::Unknown)));
+        assert_eq!(sys.modify_lcdc(LcdcFlags::OBJ_ENABLE, LcdcFlags::OBJ_ENABLE), Err(AssemblerError::ArgumentError));
+
+        sys.set_lcdc(flags).unwrap();
+        assert_eq!(sys.allocator().borrow().state.lcd, LcdState::On);
+        sys.set_bg_tilemap(TilemapSelector::Tilemap9C00).unwrap();
+        sys.set_bg_tiledata(TiledataSelector::Tiledata9000).unwrap();
+        sys.modify_lcdc(LcdcFlags::WINDOW_ENABLE | LcdcFlags::OBJ_ENABLE, LcdcFlags::empty()).unwrap();
+
+        assert_eq!(sys.set_stat_interrupts(StatFlags::LYC_EQUAL), Err(AssemblerError::UnwritableBits { reg: IoReg::Stat, value: 0x04 }));
+        sys.set_stat_interrupts(StatFlags::LYC_INTERRUPT).unwrap();
+        sys.modify_stat_interrupts(StatFlags::HBLANK_INTERRUPT, StatFlags::LYC_INTERRUPT).unwrap();
+
+        let rom = sys.build().unwrap();
+        // push af; ldh a, [LCDC]; ...; ldh [LCDC], a; pop af
+        let code = &rom.bytes[0x153..];
+        assert_eq!(&code[0..4], &[0x3e, 0xd5, 0xe0, 0x40]);
+        assert_eq!(&code[4..11], &[0xf5, 0xf0, 0x40, 0xf6, 0x08, 0xe0, 0x40]);
+        assert_eq!(&code[12..19], &[0xf5, 0xf0, 0x40, 0xe6, 0xef, 0xe0, 0x40]);
+        assert_eq!(&code[20..27], &[0xf5, 0xf0, 0x40, 0xf6, 0x22, 0xe0, 0x40]);
+        assert_eq!(&code[28..32], &[0x3e, 0x40, 0xe0, 0x41]);
+        assert_eq!(&code[32..41], &[0xf5, 0xf0, 0x41, 0xe6, 0xbf, 0xf6, 0x08, 0xe0, 0x41]);
+    }
+
+    #[test]
+    fn window_and_scroll() {
+        let mut sys = Cgb::new();
+        let dx = sys.new_hram_var(1).unwrap();
+        let dy = sys.new_hram_var(1).unwrap();
+
+        sys.set_scx(&VarOrConst::Const(Constant::Inline8(0x10))).unwrap();
+        sys.set_wx(&VarOrConst::Var(dx.clone())).unwrap();
+        assert_eq!(sys.set_scy(&VarOrConst::Const(Constant::Inline16(0x10))), Err(AssemblerError::ArgumentError));
+        sys.enable_window(TilemapSelector::Tilemap9C00).unwrap();
+
+        let unallocated = sys.new_var(1);
+        assert!(sys.schedule_scroll(&unallocated, &dy).is_err());
+        sys.schedule_scroll(&dx, &dy).unwrap();
+
+        let rom = sys.build().unwrap();
+        let code = &rom.bytes[0x153..];
+        assert_eq!(&code[0..4], &[0x3e, 0x10, 0xe0, 0x43]);
+        assert_eq!(&code[4..9], &[0xfa, 0x81, 0xff, 0xe0, 0x4b]);
+        assert_eq!(&code[9..16], &[0xf5, 0xf0, 0x40, 0xf6, 0x60, 0xe0, 0x40]);
+
+        // push af; push hl; ld a, [dx]; ld hl, SCX; add a, [hl]; ld [hl], a; pop hl; pop af
+        let scroll_x = [0xf5, 0xe5, 0xfa, 0x81, 0xff, 0x21, 0x43, 0xff, 0x86, 0x77, 0xe1, 0xf1];
+        let scroll_y = [0xf5, 0xe5, 0xfa, 0x82, 0xff, 0x21, 0x42, 0xff, 0x86, 0x77, 0xe1, 0xf1];
+        assert!(rom.bytes.windows(scroll_x.len()).any(|window| window == scroll_x));
+        assert!(rom.bytes.windows(scroll_y.len()).any(|window| window == scroll_y));
+    }
+
+    #[test]
+    fn color_conversion() {
+        let color = Color::new(31, 16, 1);
+        assert_eq!((color.r(), color.g(), color.b()), (31, 16, 1));
+        assert_eq!(color.to_bytes(), [0x1f, 0x06]);
+        assert_eq!(Color::RED | Color::GREEN, Color::new(31, 31, 0));
+        assert_eq!(Color::LIGHT_GREY.to_rgb888(), [0xad, 0xad, 0xad]);
+        assert_eq!(Color::WHITE.to_rgb888(), [0xff; 3]);
+
+        assert_eq!(Color::from_hex("#ff8000"), Ok(Color::new(31, 16, 0)));
+        assert_eq!(Color::from_hex("0000FF"), Ok(Color::BLUE));
+        assert_eq!(Color::from_hex("#ff80"), Err(ConversionError::InvalidHexColor));
+        assert_eq!(Color::from_hex("+12345"), Err(ConversionError::InvalidHexColor));
+
+        for c in 0..32 {
+            let grey = Color::new(c, c, c);
+            let [r, g, b] = grey.to_rgb888();
+            assert_eq!(Color::from_rgb888(r, g, b), grey);
+        }
+
+        // the CGB's LCD mixes channels and never gets fully bright
+        assert_eq!(Color::WHITE.to_rgb888_with(ColorCorrection::Cgb), [240; 3]);
+        assert_eq!(Color::RED.to_rgb888_with(ColorCorrection::Cgb), [201, 0, 46]);
+        for color in [Color::new(20, 5, 9), Color::new(3, 28, 12), Color::DARK_GREY, Color::BLACK] {
+            let [r, g, b] = color.to_rgb888_with(ColorCorrection::Cgb);
+            assert_eq!(Color::from_rgb888_with(r, g, b, ColorCorrection::Cgb), color);
+        }
+    }
+
+    #[test]
+    fn palette_import() {
+        let jasc = "JASC-PAL\r\n0100\r\n3\r\n255 255 255\r\n0 0 0\r\n255 0 0\r\n";
+        let palettes = palette::parse_jasc(jasc).unwrap();
+        assert_eq!(palettes, [[Color::WHITE, Color::BLACK, Color::RED, Color::BLACK]]);
+        let jasc = "JASC-PAL\r\n0100\r\n5\r\n255 255 255\r\n0 0 0\r\n255 0 0\r\n0 255 0\r\n0 0 255\r\n";
+        assert_eq!(palette::parse_jasc(jasc), Err(PaletteError::TooManyColors(5)));
+        assert_eq!(palette::parse_jasc("JASC-PAL\n0100\n3\n0 0 0\n"), Err(PaletteError::CountMismatch { expected: 3, len: 1 }));
+        assert_eq!(palette::parse_jasc("JASC-PAL\n0100\n2\n0 0 0\n0 0\n"), Err(PaletteError::InvalidLine { line: 5 }));
+        assert_eq!(palette::parse_jasc("GIMP Palette\n"), Err(PaletteError::MissingHeader));
+
+        let gpl = "GIMP Palette\nName: Test\nColumns: 2\n#\n255   0   0\tRed\n  0 255   0\tGreen\n  0   0 255\tBlue\n";
+        assert_eq!(palette::parse_gpl(gpl), Ok(vec![
+            [Color::RED, Color::GREEN, Color::BLACK, Color::BLACK],
+            [Color::BLUE, Color::BLACK, Color::BLACK, Color::BLACK],
+        ]));
+        assert_eq!(palette::parse_gpl("GIMP Palette\nColumns: 5\n0 0 0\n"), Err(PaletteError::TooManyColors(5)));
+
+        let many = (0..33).fold(String::from("GIMP Palette\n"), |acc, _| acc + "0 0 0\n");
+        assert_eq!(palette::parse_gpl(&many), Err(PaletteError::TooManyPalettes(9)));
+
+        let raw = [0xff, 0x7f, 0x1f, 0x00, 0xe0, 0x03, 0x00, 0xfc];
+        assert_eq!(palette::parse_bgr555(&raw), Ok(vec![[Color::WHITE, Color::RED, Color::GREEN, Color::BLUE]]));
+        assert_eq!(palette::parse_bgr555(&raw[..3]), Err(PaletteError::OddLength(3)));
+        assert_eq!(palette::parse_bgr555(&[]), Err(PaletteError::Empty));
+
+        let path = std::env::temp_dir().join("gleeby_palette_import.gpl");
+        std::fs::write(&path, gpl).unwrap();
+        let loaded = palette::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, palette::parse_gpl(gpl));
+        assert_eq!(palette::load(std::env::temp_dir().join("gleeby_missing.pal")), Err(PaletteError::Io(std::io::ErrorKind::NotFound)));
+    }
+
+    #[test]
+    fn palette_fade() {
+        assert_eq!(lerp(Color::WHITE, Color::BLACK, 1, 2), Color::new(15, 15, 15));
+        assert_eq!(lerp(Color::BLACK, Color::new(31, 10, 1), 1, 3), Color::new(10, 3, 0));
+        assert_eq!(lerp(Color::RED, Color::BLUE, 4, 4), Color::BLUE);
+
+        let greys = [Color::WHITE, Color::LIGHT_GREY, Color::DARK_GREY, Color::BLACK];
+        let mut from = Palettes::default();
+        from.set(PaletteKind::Background, CgbPalette::_0, greys);
+        from.set(PaletteKind::Object, CgbPalette::_3, [Color::RED; 4]);
+
+        let mut flash = PaletteFade::new(from, &FadeTarget::WHITE, 2);
+        flash.wait(1).then(&FadeTarget::Palettes(from), 2);
+        assert_eq!(flash.frames.len(), 5);
+        assert_eq!(flash.frames[0].len(), 2);
+        assert_eq!(flash.frames[0][1], (PaletteKind::Object, CgbPalette::_3, [Color::new(31, 16, 16); 4]));
+        assert!(flash.frames[2].is_empty());
+        assert_eq!(flash.end, from);
+
+        let mut sys = Cgb::new();
+        assert_eq!(sys.fade_palettes(FadeTarget::BLACK, 2), Err(AssemblerError::FadeUninitialized));
+        sys.init_fade().unwrap();
+        sys.assume_vblank();
+        sys.set_palette(CgbPalette::_0, greys).unwrap();
+        assert_eq!(sys.fade_palettes(FadeTarget::BLACK, 0), Err(AssemblerError::ArgumentError));
+        sys.fade_palettes(FadeTarget::BLACK, 2).unwrap();
+        assert_eq!(sys.allocator().borrow().state.palettes.get(PaletteKind::Background, CgbPalette::_0), Some([Color::BLACK; 4]));
+        sys.schedule_fade();
+
+        let rom = sys.build().unwrap();
+        let table = [
+            0x01, 0x68, 0x80, 0xef, 0x3d, 0x4a, 0x29, 0xa5, 0x14, 0x00, 0x00,
+            0x01, 0x68, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xff,
+        ];
+        assert!(rom.bytes.windows(table.len()).any(|window| window == table));
+
+        // spec register and selector, then the colors through the data register unrolled
+        let mut upload = vec![0x2a, 0x4f, 0x2a, 0xe2, 0x0c];
+        upload.extend([0x2a, 0xe2].repeat(8));
+        upload.extend([0x05, 0x20, 0xe8]);
+        assert!(rom.bytes.windows(upload.len()).any(|window| window == upload));
+
+        // walk the stepper with all 16 palettes changing: the palette loop is taken while b counts down, no other
+        // branch is, and it has to finish within the 10 lines of VBlank
+        let stepper = [0xf5, 0xc5, 0xe5, 0xfa, 0x01, 0xc0, 0xb7, 0x28];
+        let mut pc = rom.bytes.windows(stepper.len()).position(|window| window == stepper).unwrap();
+        let (mut b, mut cycles) = (0u8, 0);
+        loop {
+            let (len, taken) = match rom.bytes[pc] {
+                0xfa | 0xea => (3, 4),
+                0xfe => (2, 2),
+                0x28 => (2, 2),
+                0x20 if b != 0 => (2 + rom.bytes[pc + 1] as i8 as isize, 3),
+                0x20 => (2, 2),
+                0x18 => (2 + rom.bytes[pc + 1] as i8 as isize, 3),
+                0xf5 | 0xc5 | 0xe5 => (1, 4),
+                0xf1 | 0xc1 | 0xe1 => (1, 3),
+                0x2a | 0xe2 => (1, 2),
+                0x47 => { b = 16; (1, 1) },
+                0x05 => { b -= 1; (1, 1) },
+                0xb7 | 0x67 | 0x6f | 0x4f | 0x0c | 0x7d | 0x7c => (1, 1),
+                op => panic!("unexpected opcode {op:02x} in the stepper"),
+            };
+            cycles += taken;
+            if rom.bytes[pc] == 0xf1 {
+                break;
+            }
+            pc = (pc as isize + len) as usize;
+        }
+        assert!(cycles < 10 * 114, "{cycles} M-cycles");
+
+        // the tables can't spill into ROMX either, and a failed fade leaves the tracked palettes alone
+        let mut sys = Cgb::new();
+        sys.init_fade().unwrap();
+        sys.new_stored_const(&[0; 0x2000]).unwrap();
+        assert_eq!(sys.fade_palettes(FadeTarget::BLACK, 2), Err(AssemblerError::AllocError(ConstAllocError::OutOfMemory)));
+        assert_eq!(sys.allocator().borrow().bank_count(), 2);
+        assert_eq!(sys.allocator().borrow().state.palettes.get(PaletteKind::Background, CgbPalette::_0), None);
+
+        assert_eq!(Dmg::new().init_fade(), Err(AssemblerError::CgbOnly));
+    }
+
+    #[test]
+    fn image_import() {
+        // 16x8, 24 bits per pixel in BGR order, stored bottom row first
+        let (width, height) = (16u32, 8u32);
+        let pixel = |x: u32| if x < 4 { [0, 0, 0xff] } else if x < 12 { [0xff; 3] } else { [0; 3] };
+        let mut bmp = Vec::new();
+        bmp.extend_from_slice(b"BM");
+        bmp.extend_from_slice(&(54 + width * height * 3).to_le_bytes());
+        bmp.extend_from_slice(&[0, 0, 0, 0, 54, 0, 0, 0, 40, 0, 0, 0]);
+        bmp.extend_from_slice(&(width as i32).to_le_bytes());
+        bmp.extend_from_slice(&(height as i32).to_le_bytes());
+        bmp.extend_from_slice(&[1, 0, 24, 0]);
+        bmp.extend_from_slice(&[0; 24]);
+        for _ in 0..height {
+            for x in 0..width {
+                bmp.extend_from_slice(&pixel(x));
+            }
+        }
+
+        let image = Image::from_bmp(&bmp).unwrap();
+        assert_eq!((image.width, image.height), (16, 8));
+        assert_eq!(image.pixels[0], Color::RED);
+        assert_eq!(image.pixels[15], Color::BLACK);
+
+        let imported = image.import(Model::Cgb).unwrap();
+        assert_eq!(imported.tiles.len(), 2);
+        assert_eq!(imported.palettes, [[Color::WHITE, Color::RED, Color::BLACK, Color::BLACK]]);
+        assert_eq!(imported.tiles[0].pixel_data[0].pixel_data, (0b11110000, 0));
+        assert_eq!(imported.tiles[1].pixel_data[0].pixel_data, (0, 0b00001111));
+        assert_eq!(&imported.tilemap[0][..3], [0, 1, 0]);
+        assert_eq!(imported.attributes[0][1].flags, TileAttributeFlags::empty());
+
+        let mirrored: Vec<Color> = image.pixels.chunks(16).flat_map(|row| row[..8].iter().chain(row[..8].iter().rev()).copied().collect::<Vec<_>>()).collect();
+        let mirrored = Image { pixels: mirrored, ..image.clone() };
+        let imported = mirrored.import(Model::Cgb).unwrap();
+        assert_eq!(imported.tiles.len(), 1);
+        assert_eq!(imported.tilemap[0][1], 0);
+        assert_eq!(imported.attributes[0][1].flags, TileAttributeFlags::X_FLIP);
+        assert_eq!(mirrored.import(Model::Dmg).unwrap().tiles.len(), 2);
+
+        let mut ppm = b"P6\n# five colors\n8 8\n255\n".to_vec();
+        for idx in 0..64 {
+            ppm.extend_from_slice(&[[0, 0, 0], [255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]][idx % 5]);
+        }
+        let ppm = Image::from_ppm(&ppm).unwrap();
+        assert_eq!(ppm.import(Model::Cgb), Err(ImageError::Tiles(vec![TileError { x: 0, y: 0, kind: TileErrorKind::TooManyColors(5) }])));
+        assert_eq!(Image::from_ppm(b"P6 8 8 255\n"), Err(ImageError::Truncated));
+        assert_eq!(Image { width: 12, height: 8, pixels: vec![Color::BLACK; 96] }.import(Model::Cgb), Err(ImageError::NotTileAligned { width: 12, height: 8 }));
+
+        // two palettes of 4 colors each on DMG, which only has one
+        let split = Image { width: 16, height: 8, pixels: (0..128).map(|idx| Color::new((idx % 16 / 8 * 4 + idx % 4) as u8, 0, 0)).collect() };
+        assert_eq!(split.import(Model::Cgb).unwrap().palettes.len(), 2);
+        assert_eq!(split.import(Model::Dmg), Err(ImageError::Tiles(vec![TileError { x: 1, y: 0, kind: TileErrorKind::NoPaletteLeft }])));
+
+        // on DMG every color is drawn with the closest grey, not just the next free index
+        let two = Image { width: 8, height: 8, pixels: (0..64).map(|idx| if idx % 8 < 4 { Color::new(30, 30, 30) } else { Color::BLACK }).collect() };
+        let imported = two.import(Model::Dmg).unwrap();
+        assert_eq!(imported.palettes, [[Color::WHITE, Color::LIGHT_GREY, Color::DARK_GREY, Color::BLACK]]);
+        assert_eq!(imported.tiles[0].pixel_data[0].pixel_data, (0b00001111, 0b00001111));
+        assert_eq!(two.import(Model::Cgb).unwrap().palettes, [[Color::new(30, 30, 30), Color::BLACK, Color::BLACK, Color::BLACK]]);
+
+        let mut sys = Cgb::new();
+        sys.load_image(&image.import(Model::Cgb).unwrap(), TiledataSelector::Tiledata8000, TilemapSelector::Tilemap9800).unwrap();
+        sys.build().unwrap();
+
+        let mut sys = Dmg::new();
+        assert_eq!(sys.load_image(&image.import(Model::Cgb).unwrap(), TiledataSelector::Tiledata8000, TilemapSelector::Tilemap9800), Err(AssemblerError::CgbOnly));
+        sys.load_image(&image.import(Model::Dmg).unwrap(), TiledataSelector::Tiledata9000, TilemapSelector::Tilemap9800).unwrap();
+        sys.build().unwrap();
+    }
 }
@@ -1,8 +1,307 @@
+use crate::model::Model;
+
 pub type Addr = u16;
+
+pub const VRAM_BASE: Addr = 0x8000;
+pub const VRAM_SIZE: u16 = 0x2000;
+
+/// VRAM DMA moves data in blocks of this many bytes, and needs both ends aligned to it
+pub const VRAM_DMA_BLOCK: u16 = 0x10;
+/// Longest VRAM DMA transfer, 128 blocks
+pub const VRAM_DMA_MAX_LEN: u16 = 0x800;
+
+/// Object attribute memory, 40 sprites of 4 bytes each
+pub const OAM_BASE: Addr = 0xfe00;
+pub const OAM_SIZE: u16 = 0xa0;
+
+/// 32 4-bit samples played by the wave channel, high nibble first
+pub const WAVE_RAM_BASE: Addr = 0xff30;
+pub const WAVE_RAM_SIZE: u16 = 0x10;
+
+/// Every hardware register from 0xff00 up, see [IoReg::info] for what can be done with each
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum IoReg {
+    /// Joypad, bits 5-4 select the buttons or d-pad, bits 3-0 read them (0 is pressed)
+    P1 = 0xff00,
+    /// Serial transfer data
+    Sb = 0xff01,
+    /// Serial transfer control, bit 1 (clock speed) only does anything on CGB
+    Sc = 0xff02,
+    /// Divider, counts up at 16384 Hz, any write resets it
+    Div = 0xff04,
+    /// Timer counter, requests the Timer interrupt on overflow
+    Tima = 0xff05,
+    /// Timer modulo, loaded into TIMA on overflow
+    Tma = 0xff06,
+    /// Timer control
+    Tac = 0xff07,
+    /// Pending interrupts
+    If = 0xff0f,
+    /// Pulse 1 sweep
+    Nr10 = 0xff10,
+    /// Pulse 1 duty and length
+    Nr11 = 0xff11,
+    /// Pulse 1 volume envelope
+    Nr12 = 0xff12,
+    /// Pulse 1 period low
+    Nr13 = 0xff13,
+    /// Pulse 1 trigger, length enable and period high
+    Nr14 = 0xff14,
+    /// Pulse 2 duty and length
+    Nr21 = 0xff16,
+    /// Pulse 2 volume envelope
+    Nr22 = 0xff17,
+    /// Pulse 2 period low
+    Nr23 = 0xff18,
+    /// Pulse 2 trigger, length enable and period high
+    Nr24 = 0xff19,
+    /// Wave DAC enable
+    Nr30 = 0xff1a,
+    /// Wave length
+    Nr31 = 0xff1b,
+    /// Wave output level
+    Nr32 = 0xff1c,
+    /// Wave period low
+    Nr33 = 0xff1d,
+    /// Wave trigger, length enable and period high
+    Nr34 = 0xff1e,
+    /// Noise length
+    Nr41 = 0xff20,
+    /// Noise volume envelope
+    Nr42 = 0xff21,
+    /// Noise frequency and randomness
+    Nr43 = 0xff22,
+    /// Noise trigger and length enable
+    Nr44 = 0xff23,
+    /// Master volume and VIN panning
+    Nr50 = 0xff24,
+    /// Channel panning
+    Nr51 = 0xff25,
+    /// Audio master enable and channel status
+    Nr52 = 0xff26,
+    /// LCD control
     Lcdc = 0xff40,
+    /// LCD status, bits 2-0 (LYC match and PPU mode) are read-only
+    Stat = 0xff41,
+    /// Background scroll Y
+    Scy = 0xff42,
+    /// Background scroll X
+    Scx = 0xff43,
+    /// Line currently being drawn
+    Ly = 0xff44,
+    /// Line compared against LY for the STAT interrupt
+    Lyc = 0xff45,
+    /// OAM DMA source, high byte, writing it starts the transfer
+    Dma = 0xff46,
+    /// DMG background palette
+    Bgp = 0xff47,
+    /// DMG object palette 0
+    Obp0 = 0xff48,
+    /// DMG object palette 1
+    Obp1 = 0xff49,
+    /// Window Y position
+    Wy = 0xff4a,
+    /// Window X position plus 7
+    Wx = 0xff4b,
+    /// CGB speed switch, bit 7 is the current speed and bit 0 arms a switch on the next `stop`
+    Key1 = 0xff4d,
+    /// CGB VRAM bank select
+    Vbk = 0xff4f,
+    /// Unmaps the boot ROM, already written by the time the cartridge runs
+    Boot = 0xff50,
+    /// VRAM DMA source, high byte
+    Hdma1 = 0xff51,
+    /// VRAM DMA source, low byte
+    Hdma2 = 0xff52,
+    /// VRAM DMA destination, high byte
+    Hdma3 = 0xff53,
+    /// VRAM DMA destination, low byte
+    Hdma4 = 0xff54,
+    /// VRAM DMA length and mode, writing it starts the transfer
+    Hdma5 = 0xff55,
+    /// CGB infrared port
+    Rp = 0xff56,
+    /// CGB background palette index
     Bcps = 0xff68,
+    /// CGB background palette data
     Bcpd = 0xff69,
+    /// CGB object palette index
+    Ocps = 0xff6a,
+    /// CGB object palette data
+    Ocpd = 0xff6b,
+    /// CGB object priority mode
+    Opri = 0xff6c,
+    /// CGB WRAM bank select
+    Svbk = 0xff70,
+    /// CGB digital output of channels 1 and 2
+    Pcm12 = 0xff76,
+    /// CGB digital output of channels 3 and 4
+    Pcm34 = 0xff77,
+    /// Enabled interrupts
+    Ie = 0xffff,
+}
+
+/// What a register allows, from Pan Docs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IoRegInfo {
+    pub name: &'static str,
+    /// Bits that read back what the hardware holds, the rest read as 1
+    pub readable: u8,
+    /// Bits writes have an effect on, 0 for read-only registers
+    pub writable: u8,
+    /// `Some` if the register only exists on that model
+    pub model: Option<Model>,
+}
+
+impl IoReg {
+    pub const ALL: [IoReg; 59] = [
+        Self::P1,
+        Self::Sb,
+        Self::Sc,
+        Self::Div,
+        Self::Tima,
+        Self::Tma,
+        Self::Tac,
+        Self::If,
+        Self::Nr10,
+        Self::Nr11,
+        Self::Nr12,
+        Self::Nr13,
+        Self::Nr14,
+        Self::Nr21,
+        Self::Nr22,
+        Self::Nr23,
+        Self::Nr24,
+        Self::Nr30,
+        Self::Nr31,
+        Self::Nr32,
+        Self::Nr33,
+        Self::Nr34,
+        Self::Nr41,
+        Self::Nr42,
+        Self::Nr43,
+        Self::Nr44,
+        Self::Nr50,
+        Self::Nr51,
+        Self::Nr52,
+        Self::Lcdc,
+        Self::Stat,
+        Self::Scy,
+        Self::Scx,
+        Self::Ly,
+        Self::Lyc,
+        Self::Dma,
+        Self::Bgp,
+        Self::Obp0,
+        Self::Obp1,
+        Self::Wy,
+        Self::Wx,
+        Self::Key1,
+        Self::Vbk,
+        Self::Boot,
+        Self::Hdma1,
+        Self::Hdma2,
+        Self::Hdma3,
+        Self::Hdma4,
+        Self::Hdma5,
+        Self::Rp,
+        Self::Bcps,
+        Self::Bcpd,
+        Self::Ocps,
+        Self::Ocpd,
+        Self::Opri,
+        Self::Svbk,
+        Self::Pcm12,
+        Self::Pcm34,
+        Self::Ie,
+    ];
+
+    pub fn addr(&self) -> Addr {
+        *self as Addr
+    }
+
+    pub fn info(&self) -> IoRegInfo {
+        let (name, readable, writable, model) = match self {
+            Self::P1 => ("P1", 0x3f, 0x30, None),
+            Self::Sb => ("SB", 0xff, 0xff, None),
+            Self::Sc => ("SC", 0x83, 0x83, None),
+            Self::Div => ("DIV", 0xff, 0xff, None),
+            Self::Tima => ("TIMA", 0xff, 0xff, None),
+            Self::Tma => ("TMA", 0xff, 0xff, None),
+            Self::Tac => ("TAC", 0x07, 0x07, None),
+            Self::If => ("IF", 0x1f, 0x1f, None),
+            Self::Nr10 => ("NR10", 0x7f, 0x7f, None),
+            Self::Nr11 => ("NR11", 0xc0, 0xff, None),
+            Self::Nr12 => ("NR12", 0xff, 0xff, None),
+            Self::Nr13 => ("NR13", 0x00, 0xff, None),
+            Self::Nr14 => ("NR14", 0x40, 0xc7, None),
+            Self::Nr21 => ("NR21", 0xc0, 0xff, None),
+            Self::Nr22 => ("NR22", 0xff, 0xff, None),
+            Self::Nr23 => ("NR23", 0x00, 0xff, None),
+            Self::Nr24 => ("NR24", 0x40, 0xc7, None),
+            Self::Nr30 => ("NR30", 0x80, 0x80, None),
+            Self::Nr31 => ("NR31", 0x00, 0xff, None),
+            Self::Nr32 => ("NR32", 0x60, 0x60, None),
+            Self::Nr33 => ("NR33", 0x00, 0xff, None),
+            Self::Nr34 => ("NR34", 0x40, 0xc7, None),
+            Self::Nr41 => ("NR41", 0x00, 0x3f, None),
+            Self::Nr42 => ("NR42", 0xff, 0xff, None),
+            Self::Nr43 => ("NR43", 0xff, 0xff, None),
+            Self::Nr44 => ("NR44", 0x40, 0xc0, None),
+            Self::Nr50 => ("NR50", 0xff, 0xff, None),
+            Self::Nr51 => ("NR51", 0xff, 0xff, None),
+            Self::Nr52 => ("NR52", 0x8f, 0x80, None),
+            Self::Lcdc => ("LCDC", 0xff, 0xff, None),
+            Self::Stat => ("STAT", 0x7f, 0x78, None),
+            Self::Scy => ("SCY", 0xff, 0xff, None),
+            Self::Scx => ("SCX", 0xff, 0xff, None),
+            Self::Ly => ("LY", 0xff, 0x00, None),
+            Self::Lyc => ("LYC", 0xff, 0xff, None),
+            Self::Dma => ("DMA", 0xff, 0xff, None),
+            Self::Bgp => ("BGP", 0xff, 0xff, None),
+            Self::Obp0 => ("OBP0", 0xff, 0xff, None),
+            Self::Obp1 => ("OBP1", 0xff, 0xff, None),
+            Self::Wy => ("WY", 0xff, 0xff, None),
+            Self::Wx => ("WX", 0xff, 0xff, None),
+            Self::Key1 => ("KEY1", 0x81, 0x01, Some(Model::Cgb)),
+            Self::Vbk => ("VBK", 0x01, 0x01, Some(Model::Cgb)),
+            Self::Boot => ("BOOT", 0x00, 0xff, None),
+            Self::Hdma1 => ("HDMA1", 0x00, 0xff, Some(Model::Cgb)),
+            Self::Hdma2 => ("HDMA2", 0x00, 0xf0, Some(Model::Cgb)),
+            Self::Hdma3 => ("HDMA3", 0x00, 0x1f, Some(Model::Cgb)),
+            Self::Hdma4 => ("HDMA4", 0x00, 0xf0, Some(Model::Cgb)),
+            Self::Hdma5 => ("HDMA5", 0xff, 0xff, Some(Model::Cgb)),
+            Self::Rp => ("RP", 0xc3, 0xc1, Some(Model::Cgb)),
+            Self::Bcps => ("BCPS", 0xbf, 0xbf, Some(Model::Cgb)),
+            Self::Bcpd => ("BCPD", 0xff, 0xff, Some(Model::Cgb)),
+            Self::Ocps => ("OCPS", 0xbf, 0xbf, Some(Model::Cgb)),
+            Self::Ocpd => ("OCPD", 0xff, 0xff, Some(Model::Cgb)),
+            Self::Opri => ("OPRI", 0x01, 0x01, Some(Model::Cgb)),
+            Self::Svbk => ("SVBK", 0x07, 0x07, Some(Model::Cgb)),
+            Self::Pcm12 => ("PCM12", 0xff, 0x00, Some(Model::Cgb)),
+            Self::Pcm34 => ("PCM34", 0xff, 0x00, Some(Model::Cgb)),
+            Self::Ie => ("IE", 0x1f, 0x1f, None),
+        };
+
+        IoRegInfo { name, readable, writable, model }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.info().writable == 0
+    }
+
+    /// Whether the register exists on `model`
+    pub fn exists_on(&self, model: Model) -> bool {
+        self.info().model.is_none_or(|only| only == model)
+    }
+}
+
+impl TryFrom<Addr> for IoReg {
+    type Error = Addr;
+
+    fn try_from(value: Addr) -> Result<Self, Self::Error> {
+        Self::ALL.into_iter().find(|reg| reg.addr() == value).ok_or(value)
+    }
 }
 
 impl From<IoReg> for u8 {
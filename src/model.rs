@@ -0,0 +1,8 @@
+/// Which Game Boy a program is built for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Model {
+    #[default]
+    Cgb,
+    /// Original Game Boy, no color palettes, VRAM bank 1, VRAM DMA or double speed
+    Dmg,
+}
@@ -1,6 +1,9 @@
 use palettes::PaletteColor;
 use tiles::TileRow;
 
+pub mod fade;
+pub mod import;
+pub mod lcd;
 pub mod palettes;
 pub mod tiles;
 pub mod objects;
@@ -9,6 +12,8 @@ pub mod objects;
 pub enum ConversionError {
     InvalidPaletteColor,
     InvalidPaletteIndex,
+    /// Not `rrggbb` or `#rrggbb`
+    InvalidHexColor,
 }
 
 impl From<[PaletteColor; 8]> for TileRow {
@@ -44,6 +49,16 @@ impl TilemapSelector {
     }
 }
 
+/// CGB VRAM bank mapped at 0x8000-0x9fff, selected through VBK
+/// 
+/// Bank 1 holds a second set of tile data, and the BG map attributes at the same addresses as the tile indices in bank 0
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum VramBank {
+    #[default]
+    _0,
+    _1,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TiledataSelector {
     Tiledata8000,
@@ -0,0 +1,109 @@
+use super::palettes::{CgbPalette, Color, PaletteKind, PaletteSelector, Palettes};
+
+/// Marks the end of a [PaletteFade] in its encoded form
+pub const FADE_END: u8 = 0xff;
+
+/// Where a [PaletteFade] takes the palettes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FadeTarget {
+    /// Every color of every palette fades to this one
+    Color(Color),
+    /// Every palette fades to its counterpart in here, the ones left unset don't change
+    Palettes(Palettes),
+}
+
+impl FadeTarget {
+    pub const BLACK: Self = Self::Color(Color::BLACK);
+    pub const WHITE: Self = Self::Color(Color::WHITE);
+
+    fn colors(&self, kind: PaletteKind, palette: CgbPalette) -> Option<[Color; 4]> {
+        match self {
+            Self::Color(color) => Some([*color; 4]),
+            Self::Palettes(palettes) => palettes.get(kind, palette),
+        }
+    }
+}
+
+/// Palette writes for each frame of a fade, interpolated at build time
+/// 
+/// Only palettes that have been written (see [Palettes]) take part. Played by
+/// [MacroAssembler::start_fade](crate::codegen::MacroAssembler::start_fade) and advanced one frame per
+/// [MacroAssembler::step_fade](crate::codegen::MacroAssembler::step_fade)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PaletteFade {
+    pub frames: Vec<Vec<(PaletteKind, CgbPalette, [Color; 4])>>,
+    /// Colors left in palette memory after the last frame
+    pub end: Palettes,
+}
+
+impl PaletteFade {
+    /// Fades from `from` to `target` over `frames` frames, the last of which writes `target` exactly
+    pub fn new(from: Palettes, target: &FadeTarget, frames: u8) -> Self {
+        let mut fade = Self { frames: Vec::new(), end: from };
+        fade.then(target, frames);
+        fade
+    }
+
+    /// Continues from where the fade ends to `target` over `frames` frames, a flash is a fade to white and back
+    pub fn then(&mut self, target: &FadeTarget, frames: u8) -> &mut Self {
+        let start = self.end;
+
+        for frame in 1..=frames {
+            let mut writes = Vec::new();
+            for kind in PaletteKind::ALL {
+                for palette in CgbPalette::ALL {
+                    let Some(from) = start.get(kind, palette) else {
+                        continue;
+                    };
+                    let to = target.colors(kind, palette).unwrap_or(from);
+                    if from == to {
+                        continue;
+                    }
+
+                    let colors = std::array::from_fn(|idx| lerp(from[idx], to[idx], frame, frames));
+                    writes.push((kind, palette, colors));
+                    self.end.set(kind, palette, colors);
+                }
+            }
+
+            self.frames.push(writes);
+        }
+
+        self
+    }
+
+    /// Holds the colors for `frames` frames
+    pub fn wait(&mut self, frames: usize) -> &mut Self {
+        self.frames.extend(std::iter::repeat_n(Vec::new(), frames));
+        self
+    }
+
+    /// Encodes the fade for the stepper: every frame is a palette count followed by that many
+    /// `(low byte of the spec register, autoincrementing selector, 8 bytes of colors)` entries, and [FADE_END] follows the last frame
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        for frame in self.frames.iter() {
+            out.push(frame.len() as u8);
+            for &(kind, palette, colors) in frame {
+                out.push(kind.spec_reg().into());
+                out.push(PaletteSelector::new(true, palette).into());
+                out.extend(colors.iter().flat_map(Color::to_bytes));
+            }
+        }
+
+        out.push(FADE_END);
+        out
+    }
+}
+
+/// Color `step` steps of `steps` from `from` to `to`, rounding each component to the nearest
+pub fn lerp(from: Color, to: Color, step: u8, steps: u8) -> Color {
+    let mix = |from: u8, to: u8| {
+        let (from, to, step, steps) = (from as i32, to as i32, step as i32, steps as i32);
+        let offset = (to - from) * step;
+        (from + (offset + offset.signum() * steps / 2) / steps) as u8
+    };
+
+    Color::new(mix(from.r(), to.r()), mix(from.g(), to.g()), mix(from.b(), to.b()))
+}
@@ -0,0 +1,4 @@
+/// Indexed and true color images from BMP and PPM files
+pub mod image;
+/// Palette files from JASC, GIMP and rgbgfx
+pub mod palette;
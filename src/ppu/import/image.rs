@@ -0,0 +1,417 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::model::Model;
+use crate::ppu::palettes::{CgbPalette, Color, PaletteColor};
+use crate::ppu::tiles::{AttributeMap, Tile, TileAttributeFlags, TileAttributes, Tilemap};
+
+use super::palette::{MAX_PALETTES, PALETTE_COLORS};
+
+/// Width and height of the BG map, in tiles
+pub const MAP_SIZE: usize = 32;
+/// Tiles a single VRAM bank holds
+pub const BANK_TILES: usize = 256;
+/// The grey [DmgPalette::DEFAULT](crate::ppu::palettes::DmgPalette::DEFAULT) shows for each color index
+const DMG_SHADES: [Color; 4] = [Color::WHITE, Color::LIGHT_GREY, Color::DARK_GREY, Color::BLACK];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImageError {
+    Io(io::ErrorKind),
+    /// Neither a BMP nor a binary PPM (`P6`)
+    UnknownFormat,
+    /// The header is cut short, has a zero size, or a pixel points past the color table
+    InvalidHeader,
+    /// Compressed BMPs, bit depths other than 1, 4, 8, 24 and 32, or PPMs with more than 8 bits per component
+    Unsupported,
+    /// The pixel data ends before the last row
+    Truncated,
+    /// Width and height, in pixels, have to be multiples of 8
+    NotTileAligned { width: usize, height: usize },
+    /// The image doesn't fit in the 32x32 tile BG map
+    TooBig { width: usize, height: usize },
+    /// Tiles that couldn't be given a palette
+    Tiles(Vec<TileError>),
+    /// `len` distinct tiles, more than the model's VRAM holds
+    TooManyTiles(usize),
+}
+
+impl Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotTileAligned { width, height } => write!(f, "NotTileAligned: {width}x{height} isn't a multiple of 8x8"),
+            Self::TooBig { width, height } => write!(f, "TooBig: {width}x{height} is bigger than 256x256"),
+            Self::Tiles(errors) => {
+                write!(f, "Tiles:")?;
+                for error in errors {
+                    write!(f, " {error}")?;
+                }
+
+                Ok(())
+            }
+            Self::TooManyTiles(len) => write!(f, "TooManyTiles: {len} distinct tiles"),
+            _ => write!(f, "{self:?}"),
+        }
+    }
+}
+
+impl From<io::Error> for ImageError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value.kind())
+    }
+}
+
+/// What's wrong with the tile at column `x`, row `y`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileError {
+    pub x: usize,
+    pub y: usize,
+    pub kind: TileErrorKind,
+}
+
+impl Display for TileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { x, y, kind } = self;
+        match kind {
+            TileErrorKind::TooManyColors(len) => write!(f, "({x}, {y}) has {len} colors"),
+            TileErrorKind::NoPaletteLeft => write!(f, "({x}, {y}) fits none of the palettes"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileErrorKind {
+    /// `len` distinct colors, a tile can only use the 4 of its palette
+    TooManyColors(usize),
+    /// Every palette the model has is taken by colors the tile doesn't share
+    NoPaletteLeft,
+}
+
+/// Decoded image, one [Color] per pixel in rows from the top left
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+/// An [Image] cut into tiles, ready for [MacroAssembler::load_image](crate::codegen::MacroAssembler::load_image)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImportedImage {
+    pub model: Model,
+    /// Width in tiles
+    pub width: usize,
+    /// Height in tiles
+    pub height: usize,
+    /// Distinct tiles, the first [BANK_TILES] go in VRAM bank 0 and the rest in bank 1
+    pub tiles: Vec<Tile>,
+    pub tilemap: Tilemap,
+    /// Palette, flips and bank of every map entry, only used on CGB
+    pub attributes: AttributeMap,
+    /// Each sorted bright to dark on CGB. On DMG this is the four greys [DmgPalette::DEFAULT](crate::ppu::palettes::DmgPalette::DEFAULT)
+    /// shows, and every color is drawn with the closest of them
+    pub palettes: Vec<[Color; 4]>,
+}
+
+impl Image {
+    /// Reads the BMP or PPM at `path`, telling them apart by their first bytes
+    pub fn load<P>(path: P) -> Result<Self, ImageError>
+            where P: AsRef<Path> {
+        let bytes = fs::read(path)?;
+
+        if bytes.starts_with(b"BM") {
+            Self::from_bmp(&bytes)
+        } else if bytes.starts_with(b"P6") {
+            Self::from_ppm(&bytes)
+        } else {
+            Err(ImageError::UnknownFormat)
+        }
+    }
+
+    /// Parses an uncompressed Windows bitmap, either indexed with 1, 4 or 8 bits per pixel or direct with 24 or 32
+    pub fn from_bmp(bytes: &[u8]) -> Result<Self, ImageError> {
+        let u16_at = |offset: usize| bytes.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+        let u32_at = |offset: usize| bytes.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+
+        if !bytes.starts_with(b"BM") {
+            return Err(ImageError::UnknownFormat);
+        }
+
+        let data = u32_at(10).ok_or(ImageError::InvalidHeader)? as usize;
+        let dib_size = u32_at(14).ok_or(ImageError::InvalidHeader)? as usize;
+        if dib_size < 40 {
+            return Err(ImageError::Unsupported);
+        }
+
+        let width = u32_at(18).ok_or(ImageError::InvalidHeader)? as i32;
+        let height = u32_at(22).ok_or(ImageError::InvalidHeader)? as i32;
+        let bpp = u16_at(28).ok_or(ImageError::InvalidHeader)? as usize;
+        let compression = u32_at(30).ok_or(ImageError::InvalidHeader)?;
+        let colors_used = u32_at(46).ok_or(ImageError::InvalidHeader)? as usize;
+
+        if compression != 0 || ![1, 4, 8, 24, 32].contains(&bpp) {
+            return Err(ImageError::Unsupported);
+        }
+        if width <= 0 || height == 0 {
+            return Err(ImageError::InvalidHeader);
+        }
+
+        // positive heights are stored bottom row first
+        let bottom_up = height > 0;
+        let (width, height) = (width as usize, height.unsigned_abs() as usize);
+
+        let color_table: Vec<Color> = if bpp <= 8 {
+            let len = if colors_used == 0 { 1 << bpp } else { colors_used };
+            let table = bytes.get(14 + dib_size..14 + dib_size + len * 4).ok_or(ImageError::InvalidHeader)?;
+            table.chunks(4).map(|bgrx| Color::from_rgb888(bgrx[2], bgrx[1], bgrx[0])).collect()
+        } else {
+            Vec::new()
+        };
+
+        let stride = (width * bpp).div_ceil(32) * 4;
+        let mut pixels = Vec::new();
+
+        for y in 0..height {
+            let stored = if bottom_up { height - 1 - y } else { y };
+            let start = data + stored * stride;
+            let row = bytes.get(start..start + stride).ok_or(ImageError::Truncated)?;
+
+            for x in 0..width {
+                let color = match bpp {
+                    24 | 32 => {
+                        let px = &row[x * bpp / 8..];
+                        Color::from_rgb888(px[2], px[1], px[0])
+                    }
+                    _ => {
+                        let bit = x * bpp;
+                        let idx = (row[bit / 8] >> (8 - bpp - bit % 8)) & ((1 << bpp) - 1) as u8;
+                        *color_table.get(idx as usize).ok_or(ImageError::InvalidHeader)?
+                    }
+                };
+
+                pixels.push(color);
+            }
+        }
+
+        Ok(Self { width, height, pixels })
+    }
+
+    /// Parses a binary PPM (`P6`), components above 8 bits aren't supported
+    pub fn from_ppm(bytes: &[u8]) -> Result<Self, ImageError> {
+        if !bytes.starts_with(b"P6") {
+            return Err(ImageError::UnknownFormat);
+        }
+
+        let mut pos = 2;
+        let mut header = [0; 3];
+
+        for field in header.iter_mut() {
+            loop {
+                match bytes.get(pos) {
+                    Some(byte) if byte.is_ascii_whitespace() => pos += 1,
+                    Some(b'#') => while bytes.get(pos).is_some_and(|&byte| byte != b'\n') { pos += 1 },
+                    Some(_) => break,
+                    None => return Err(ImageError::InvalidHeader),
+                }
+            }
+
+            let digits = bytes[pos..].iter().take_while(|byte| byte.is_ascii_digit()).count();
+            *field = std::str::from_utf8(&bytes[pos..pos + digits]).ok()
+                .and_then(|digits| digits.parse::<usize>().ok())
+                .ok_or(ImageError::InvalidHeader)?;
+            pos += digits;
+        }
+
+        let [width, height, max] = header;
+        if width == 0 || height == 0 || max == 0 {
+            return Err(ImageError::InvalidHeader);
+        }
+        if max > u8::MAX as usize {
+            return Err(ImageError::Unsupported);
+        }
+
+        // a single whitespace byte separates the header from the pixels
+        let start = pos + 1;
+        let len = width.checked_mul(height).and_then(|len| len.checked_mul(3)).ok_or(ImageError::InvalidHeader)?;
+        let data = bytes.get(start..).and_then(|data| data.get(..len)).ok_or(ImageError::Truncated)?;
+        let scale = |c: u8| (c as usize * 255 / max).min(255) as u8;
+        let pixels = data.chunks(3).map(|rgb| Color::from_rgb888(scale(rgb[0]), scale(rgb[1]), scale(rgb[2]))).collect();
+
+        Ok(Self { width, height, pixels })
+    }
+
+    /// Cuts the image into 8x8 tiles, picks palettes for them and drops duplicates
+    /// 
+    /// Each tile can use at most 4 colors, all from one palette: up to 8 of them on CGB and a single one on DMG.
+    /// Every tile breaking that is reported, not just the first. On CGB a tile that's a mirror image of an earlier one
+    /// reuses it through the flip attributes, and tiles past the first [BANK_TILES] go in VRAM bank 1
+    pub fn import(&self, model: Model) -> Result<ImportedImage, ImageError> {
+        let (width, height) = (self.width, self.height);
+        if !width.is_multiple_of(8) || !height.is_multiple_of(8) {
+            return Err(ImageError::NotTileAligned { width, height });
+        }
+        if width > MAP_SIZE * 8 || height > MAP_SIZE * 8 {
+            return Err(ImageError::TooBig { width, height });
+        }
+
+        let (columns, rows) = (width / 8, height / 8);
+        let tile_pixels = |tile: usize| {
+            let (x, y) = (tile % columns * 8, tile / columns * 8);
+            std::array::from_fn::<[Color; 8], 8, _>(|row| std::array::from_fn(|col| self.pixels[(y + row) * width + x + col]))
+        };
+
+        let mut errors = Vec::new();
+        let colors: Vec<Vec<Color>> = (0..columns * rows).map(|tile| {
+            let mut colors = Vec::new();
+            for &color in tile_pixels(tile).as_flattened() {
+                if !colors.contains(&color) {
+                    colors.push(color);
+                }
+            }
+
+            if colors.len() > PALETTE_COLORS {
+                errors.push(TileError { x: tile % columns, y: tile / columns, kind: TileErrorKind::TooManyColors(colors.len()) });
+            }
+
+            colors
+        }).collect();
+
+        if !errors.is_empty() {
+            return Err(ImageError::Tiles(errors));
+        }
+
+        let max_palettes = match model {
+            Model::Cgb => MAX_PALETTES,
+            Model::Dmg => 1,
+        };
+
+        // tiles with the most colors first, the ones with fewer usually fit in what they leave
+        let mut order: Vec<usize> = (0..colors.len()).collect();
+        order.sort_by_key(|&tile| std::cmp::Reverse(colors[tile].len()));
+
+        let mut palettes: Vec<Vec<Color>> = Vec::new();
+        let mut tile_palettes = vec![0; colors.len()];
+
+        for tile in order {
+            let set = &colors[tile];
+            let shared = |palette: &Vec<Color>| set.iter().filter(|color| palette.contains(color)).count();
+
+            let best = palettes.iter().enumerate()
+                .filter(|(_, palette)| palette.len() + set.len() - shared(palette) <= PALETTE_COLORS)
+                .max_by_key(|(idx, palette)| (shared(palette), std::cmp::Reverse(*idx)))
+                .map(|(idx, palette)| (idx, shared(palette)));
+
+            tile_palettes[tile] = match best {
+                Some((idx, shared)) if shared > 0 || palettes.len() == max_palettes => idx,
+                _ if palettes.len() < max_palettes => {
+                    palettes.push(Vec::new());
+                    palettes.len() - 1
+                }
+                _ => {
+                    errors.push(TileError { x: tile % columns, y: tile / columns, kind: TileErrorKind::NoPaletteLeft });
+                    continue;
+                }
+            };
+
+            let palette = &mut palettes[tile_palettes[tile]];
+            for &color in set {
+                if !palette.contains(&color) {
+                    palette.push(color);
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            errors.sort_by_key(|error| (error.y, error.x));
+            return Err(ImageError::Tiles(errors));
+        }
+
+        let palettes: Vec<[Color; 4]> = match model {
+            Model::Cgb => palettes.into_iter().map(|mut colors| {
+                colors.sort_by_key(|color| std::cmp::Reverse(luminance(*color)));
+
+                let mut palette = [Color::BLACK; 4];
+                palette[..colors.len()].copy_from_slice(&colors);
+                palette
+            }).collect(),
+            Model::Dmg => vec![DMG_SHADES],
+        };
+
+        const INDICES: [PaletteColor; 4] = [PaletteColor::_0, PaletteColor::_1, PaletteColor::_2, PaletteColor::_3];
+
+        let mut tiles = Vec::new();
+        let mut seen: HashMap<[u8; 16], usize> = HashMap::new();
+        let mut tilemap = [0; MAP_SIZE * MAP_SIZE];
+        let mut attributes = AttributeMap::default();
+
+        for (tile, &palette_idx) in tile_palettes.iter().enumerate() {
+            let palette = &palettes[palette_idx];
+            let pixels = tile_pixels(tile).map(|row| row.map(|color| {
+                let idx = match model {
+                    Model::Cgb => palette.iter().position(|&c| c == color).unwrap_or_default(),
+                    Model::Dmg => (0..DMG_SHADES.len()).min_by_key(|&idx| distance(DMG_SHADES[idx], color)).unwrap_or_default(),
+                };
+                INDICES[idx]
+            }));
+            let data = Tile::new(pixels);
+
+            let mut variants = vec![(data, TileAttributeFlags::empty())];
+            if model == Model::Cgb {
+                variants.push((data.flipped_x(), TileAttributeFlags::X_FLIP));
+                variants.push((data.flipped_y(), TileAttributeFlags::Y_FLIP));
+                variants.push((data.flipped_x().flipped_y(), TileAttributeFlags::X_FLIP | TileAttributeFlags::Y_FLIP));
+            }
+
+            let (idx, mut flags) = variants.iter()
+                .find_map(|(variant, flip)| seen.get(&variant.as_bytes()).map(|&idx| (idx, *flip)))
+                .unwrap_or_else(|| {
+                    tiles.push(data);
+                    seen.insert(data.as_bytes(), tiles.len() - 1);
+                    (tiles.len() - 1, TileAttributeFlags::empty())
+                });
+
+            if idx >= BANK_TILES {
+                flags |= TileAttributeFlags::BANK1;
+            }
+
+            let entry = tile / columns * MAP_SIZE + tile % columns;
+            tilemap[entry] = (idx % BANK_TILES) as u8;
+            attributes.map[entry] = TileAttributes {
+                flags,
+                palette: CgbPalette::ALL[palette_idx],
+            };
+        }
+
+        let max_tiles = match model {
+            Model::Cgb => BANK_TILES * 2,
+            Model::Dmg => BANK_TILES,
+        };
+        if tiles.len() > max_tiles {
+            return Err(ImageError::TooManyTiles(tiles.len()));
+        }
+
+        Ok(ImportedImage {
+            model,
+            width: columns,
+            height: rows,
+            tiles,
+            tilemap: tilemap.into(),
+            attributes,
+            palettes,
+        })
+    }
+}
+
+/// Perceived brightness, weighting green the most
+fn luminance(color: Color) -> u32 {
+    299 * color.r() as u32 + 587 * color.g() as u32 + 114 * color.b() as u32
+}
+
+/// Squared distance between two colors, treating the components as coordinates
+fn distance(lhs: Color, rhs: Color) -> u32 {
+    [(lhs.r(), rhs.r()), (lhs.g(), rhs.g()), (lhs.b(), rhs.b())].into_iter()
+        .map(|(lhs, rhs)| (lhs as i32 - rhs as i32).pow(2) as u32)
+        .sum()
+}
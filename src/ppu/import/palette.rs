@@ -0,0 +1,177 @@
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::ppu::palettes::Color;
+
+/// Colors in a CGB palette
+pub const PALETTE_COLORS: usize = 4;
+/// Palettes of each kind the CGB has room for
+pub const MAX_PALETTES: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaletteError {
+    Io(io::ErrorKind),
+    /// The file doesn't start with the header of its format
+    MissingHeader,
+    /// Line `line`, counting from 1, isn't a color or anything else the format allows
+    InvalidLine { line: usize },
+    /// The JASC header promised `expected` colors, but the file has `len`
+    CountMismatch { expected: usize, len: usize },
+    /// Raw palettes are 2 bytes per color
+    OddLength(usize),
+    /// The file asks for palettes of `len` colors, more than the CGB's 4
+    TooManyColors(usize),
+    /// The file holds `len` palettes, more than the CGB's 8
+    TooManyPalettes(usize),
+    /// Not a single color
+    Empty,
+}
+
+impl Display for PaletteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidLine { line } => write!(f, "InvalidLine: Couldn't parse line {line}"),
+            Self::CountMismatch { expected, len } => write!(f, "CountMismatch: Expected {expected} colors, got {len}"),
+            Self::TooManyColors(len) => write!(f, "TooManyColors: Expected at most {PALETTE_COLORS} colors per palette, got {len}"),
+            Self::TooManyPalettes(len) => write!(f, "TooManyPalettes: Expected at most {MAX_PALETTES} palettes, got {len}"),
+            _ => write!(f, "{self:?}"),
+        }
+    }
+}
+
+impl From<io::Error> for PaletteError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value.kind())
+    }
+}
+
+/// Reads the palette file at `path` into `[Color; 4]` sets ready for [MacroAssembler::set_palette](crate::codegen::MacroAssembler::set_palette)
+/// 
+/// Colors are taken four at a time in file order unless the format says otherwise, and a palette left short is padded with [Color::BLACK].
+/// `.gpl` files are parsed as GIMP palettes, anything else as JASC if it starts with the JASC header and as raw BGR555 otherwise
+pub fn load<P>(path: P) -> Result<Vec<[Color; 4]>, PaletteError>
+        where P: AsRef<Path> {
+    let path = path.as_ref();
+    let bytes = fs::read(path)?;
+
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gpl")) {
+        parse_gpl(&String::from_utf8_lossy(&bytes))
+    } else if bytes.starts_with(JASC_HEADER.as_bytes()) {
+        parse_jasc(&String::from_utf8_lossy(&bytes))
+    } else {
+        parse_bgr555(&bytes)
+    }
+}
+
+const JASC_HEADER: &str = "JASC-PAL";
+
+/// Parses a JASC (Paint Shop Pro) palette: the header, version `0100`, the color count, then one `r g b` line per color
+/// 
+/// The file is a single palette, so a count above 4 is [PaletteError::TooManyColors]
+pub fn parse_jasc(src: &str) -> Result<Vec<[Color; 4]>, PaletteError> {
+    let mut lines = src.lines().map(str::trim).enumerate().filter(|(_, line)| !line.is_empty());
+
+    if lines.next().is_none_or(|(_, line)| line != JASC_HEADER) {
+        return Err(PaletteError::MissingHeader);
+    }
+
+    match lines.next() {
+        Some((_, "0100")) => {},
+        Some((idx, _)) => return Err(PaletteError::InvalidLine { line: idx + 1 }),
+        None => return Err(PaletteError::Empty),
+    }
+
+    let expected = match lines.next() {
+        Some((idx, line)) => line.parse().map_err(|_| PaletteError::InvalidLine { line: idx + 1 })?,
+        None => return Err(PaletteError::Empty),
+    };
+
+    let colors = lines
+        .map(|(idx, line)| parse_rgb(line).ok_or(PaletteError::InvalidLine { line: idx + 1 }))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if colors.len() != expected {
+        return Err(PaletteError::CountMismatch { expected, len: colors.len() });
+    }
+    if expected > PALETTE_COLORS {
+        return Err(PaletteError::TooManyColors(expected));
+    }
+
+    group(&colors, PALETTE_COLORS)
+}
+
+/// Parses a GIMP palette
+/// 
+/// A `Columns` header sets how many colors go in each palette, defaulting to 4. Color names after the components are ignored
+pub fn parse_gpl(src: &str) -> Result<Vec<[Color; 4]>, PaletteError> {
+    let mut lines = src.lines().map(str::trim).enumerate();
+
+    if lines.next().is_none_or(|(_, line)| line != "GIMP Palette") {
+        return Err(PaletteError::MissingHeader);
+    }
+
+    let mut columns = PALETTE_COLORS;
+    let mut colors = Vec::new();
+    for (idx, line) in lines {
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Name:") {
+            continue;
+        }
+
+        if let Some(value) = line.strip_prefix("Columns:") {
+            columns = value.trim().parse().map_err(|_| PaletteError::InvalidLine { line: idx + 1 })?;
+            if columns == 0 {
+                columns = PALETTE_COLORS;
+            }
+            continue;
+        }
+
+        colors.push(parse_rgb(line).ok_or(PaletteError::InvalidLine { line: idx + 1 })?);
+    }
+
+    group(&colors, columns)
+}
+
+/// Parses little endian BGR555 colors, like the `.pal` files rgbgfx writes. Bit 15 is ignored
+pub fn parse_bgr555(bytes: &[u8]) -> Result<Vec<[Color; 4]>, PaletteError> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err(PaletteError::OddLength(bytes.len()));
+    }
+
+    let colors: Vec<Color> = bytes.chunks_exact(2)
+        .map(|bytes| Color(u16::from_le_bytes([bytes[0], bytes[1]]) & 0x7fff))
+        .collect();
+
+    group(&colors, PALETTE_COLORS)
+}
+
+/// Three whitespace separated 8-bit components, followed by anything
+fn parse_rgb(line: &str) -> Option<Color> {
+    let mut components = line.split_whitespace().map(|component| component.parse::<u8>());
+
+    match (components.next(), components.next(), components.next()) {
+        (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => Some(Color::from_rgb888(r, g, b)),
+        _ => None,
+    }
+}
+
+fn group(colors: &[Color], per_palette: usize) -> Result<Vec<[Color; 4]>, PaletteError> {
+    if per_palette > PALETTE_COLORS {
+        return Err(PaletteError::TooManyColors(per_palette));
+    }
+    if colors.is_empty() {
+        return Err(PaletteError::Empty);
+    }
+
+    let palettes = colors.len().div_ceil(per_palette);
+    if palettes > MAX_PALETTES {
+        return Err(PaletteError::TooManyPalettes(palettes));
+    }
+
+    Ok(colors.chunks(per_palette).map(|chunk| {
+        let mut palette = [Color::BLACK; 4];
+        palette[..chunk.len()].copy_from_slice(chunk);
+        palette
+    }).collect())
+}
@@ -0,0 +1,116 @@
+use bitflags::bitflags;
+
+use super::{objects::ObjSize, TiledataSelector, TilemapSelector};
+
+/// WX holds the window's left edge plus this
+pub const WINDOW_X_OFFSET: u8 = 7;
+
+bitflags! {
+    /// Bits of LCDC
+    /// 
+    /// The area and map bits are cleared for the lower address, see [LcdcFlags::tiledata], [LcdcFlags::bg_tilemap]
+    /// and [LcdcFlags::window_tilemap] to build them from selectors
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+    pub struct LcdcFlags: u8 {
+        /// Turning the LCD off outside VBlank can damage DMG hardware
+        const LCD_ENABLE        = 0b10000000;
+        /// Window map at 0x9c00 instead of 0x9800
+        const WINDOW_TILEMAP    = 0b01000000;
+        const WINDOW_ENABLE     = 0b00100000;
+        /// BG and window tile data at 0x8000 instead of 0x9000
+        const TILEDATA          = 0b00010000;
+        /// BG map at 0x9c00 instead of 0x9800
+        const BG_TILEMAP        = 0b00001000;
+        /// 8x16 objects instead of 8x8
+        const OBJ_SIZE          = 0b00000100;
+        const OBJ_ENABLE        = 0b00000010;
+        /// Hides BG and window on DMG, makes objects draw over them on CGB
+        const BG_ENABLE         = 0b00000001;
+    }
+}
+
+impl LcdcFlags {
+    pub fn tiledata(tiledata: TiledataSelector) -> Self {
+        match tiledata {
+            TiledataSelector::Tiledata8000 => Self::TILEDATA,
+            TiledataSelector::Tiledata9000 => Self::empty(),
+        }
+    }
+
+    pub fn bg_tilemap(tilemap: TilemapSelector) -> Self {
+        match tilemap {
+            TilemapSelector::Tilemap9800 => Self::empty(),
+            TilemapSelector::Tilemap9C00 => Self::BG_TILEMAP,
+        }
+    }
+
+    pub fn window_tilemap(tilemap: TilemapSelector) -> Self {
+        match tilemap {
+            TilemapSelector::Tilemap9800 => Self::empty(),
+            TilemapSelector::Tilemap9C00 => Self::WINDOW_TILEMAP,
+        }
+    }
+
+    pub fn obj_size(size: ObjSize) -> Self {
+        match size {
+            ObjSize::_8x8 => Self::empty(),
+            ObjSize::_8x16 => Self::OBJ_SIZE,
+        }
+    }
+
+    pub fn get_tiledata(&self) -> TiledataSelector {
+        if self.contains(Self::TILEDATA) {
+            TiledataSelector::Tiledata8000
+        } else {
+            TiledataSelector::Tiledata9000
+        }
+    }
+
+    pub fn get_bg_tilemap(&self) -> TilemapSelector {
+        if self.contains(Self::BG_TILEMAP) {
+            TilemapSelector::Tilemap9C00
+        } else {
+            TilemapSelector::Tilemap9800
+        }
+    }
+
+    pub fn get_window_tilemap(&self) -> TilemapSelector {
+        if self.contains(Self::WINDOW_TILEMAP) {
+            TilemapSelector::Tilemap9C00
+        } else {
+            TilemapSelector::Tilemap9800
+        }
+    }
+
+    pub fn get_obj_size(&self) -> ObjSize {
+        if self.contains(Self::OBJ_SIZE) {
+            ObjSize::_8x16
+        } else {
+            ObjSize::_8x8
+        }
+    }
+}
+
+bitflags! {
+    /// Bits of STAT, the interrupt sources are ORed into the single STAT interrupt
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+    pub struct StatFlags: u8 {
+        /// Requests the STAT interrupt when LY reaches LYC
+        const LYC_INTERRUPT     = 0b01000000;
+        /// Requests the STAT interrupt when the PPU starts scanning OAM (mode 2)
+        const OAM_INTERRUPT     = 0b00100000;
+        /// Requests the STAT interrupt on entering VBlank (mode 1)
+        const VBLANK_INTERRUPT  = 0b00010000;
+        /// Requests the STAT interrupt on entering HBlank (mode 0)
+        const HBLANK_INTERRUPT  = 0b00001000;
+        /// Read-only, set while LY equals LYC
+        const LYC_EQUAL         = 0b00000100;
+    }
+}
+
+impl StatFlags {
+    pub const INTERRUPTS: Self = Self::LYC_INTERRUPT
+        .union(Self::OAM_INTERRUPT)
+        .union(Self::VBLANK_INTERRUPT)
+        .union(Self::HBLANK_INTERRUPT);
+}
@@ -26,6 +26,25 @@ pub struct ObjAttributes {
     pub cgb_palette: CgbPalette,
 }
 
+/// Object height, the same for every object and selected through LCDC
+/// 
+/// 8x16 objects use the even tile `tile & 0xfe` on top and the next one below it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ObjSize {
+    #[default]
+    _8x8,
+    _8x16,
+}
+
+impl ObjSize {
+    pub fn height(&self) -> u8 {
+        match self {
+            Self::_8x8 => 8,
+            Self::_8x16 => 16,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Pos2 {
     pub x: u8,
@@ -49,6 +68,64 @@ impl Sprite {
     }
 }
 
+/// One object of a [Metasprite], positioned relative to the metasprite's origin
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MetaspriteEntry {
+    pub dx: i8,
+    pub dy: i8,
+    pub tile: TileIdx,
+    pub attr: ObjAttributes,
+}
+
+/// A group of 8x8 objects moved together, like a character bigger than a single tile
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct Metasprite {
+    pub entries: Vec<MetaspriteEntry>,
+}
+
+impl Metasprite {
+    pub fn new(entries: Vec<MetaspriteEntry>) -> Self {
+        Self { entries }
+    }
+
+    /// Adds an object at (`dx`, `dy`) from the origin
+    pub fn entry(&mut self, dx: i8, dy: i8, tile: TileIdx, attr: ObjAttributes) -> &mut Self {
+        self.entries.push(MetaspriteEntry { dx, dy, tile, attr });
+        self
+    }
+
+    /// Mirrors the whole group around its origin, using the `X_FLIP` and `Y_FLIP` bits of `flip`
+    /// 
+    /// Each entry's offset is mirrored and its own flip flags toggled, so an entry covering `0..8` ends up covering `-8..0`.
+    /// Vertically, entries are `size` tall
+    pub fn flipped(&self, flip: ObjAttributeFlags, size: ObjSize) -> Self {
+        let flip = flip & (ObjAttributeFlags::X_FLIP | ObjAttributeFlags::Y_FLIP);
+        let entries = self.entries.iter().map(|entry| {
+            let mut entry = *entry;
+
+            if flip.contains(ObjAttributeFlags::X_FLIP) {
+                entry.dx = entry.dx.wrapping_neg().wrapping_sub(8);
+            }
+
+            if flip.contains(ObjAttributeFlags::Y_FLIP) {
+                entry.dy = entry.dy.wrapping_neg().wrapping_sub(size.height() as i8);
+            }
+
+            entry.attr.flags.toggle(flip);
+            entry
+        }).collect();
+
+        Self { entries }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
 
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
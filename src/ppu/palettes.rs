@@ -1,5 +1,7 @@
 use std::ops::BitOr;
 
+use crate::memory::IoReg;
+
 use super::ConversionError;
 
 #[repr(u8)]
@@ -43,6 +45,8 @@ impl TryFrom<u8> for CgbPalette {
 }
 
 impl CgbPalette {
+    pub const ALL: [CgbPalette; 8] = [Self::_0, Self::_1, Self::_2, Self::_3, Self::_4, Self::_5, Self::_6, Self::_7];
+
     pub fn offset(&self) -> u8 {
         let idx: u8 = (*self).into();
         idx * 8
@@ -88,12 +92,12 @@ pub struct PaletteSelector {
 
 impl From<PaletteSelector> for u8 {
     fn from(value: PaletteSelector) -> Self {
-        let idx: u8 = value.palette.into();
+        let offset = value.palette.offset();
 
         if value.autoincrement {
-            0x80 | (idx & 0x1f)
+            0x80 | (offset & 0x3f)
         } else {
-            idx & 0x1f
+            offset & 0x3f
         }
     }
 }
@@ -104,17 +108,220 @@ impl PaletteSelector {
     }
 }
 
+/// Which of the two sets of 8 CGB palettes to write to
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaletteKind {
+    Background,
+    /// Color 0 of every object palette is transparent
+    Object,
+}
+
+impl PaletteKind {
+    pub const ALL: [PaletteKind; 2] = [Self::Background, Self::Object];
+
+    /// Register selecting the palette byte, takes a [PaletteSelector]
+    pub fn spec_reg(&self) -> IoReg {
+        match self {
+            Self::Background => IoReg::Bcps,
+            Self::Object => IoReg::Ocps,
+        }
+    }
+
+    /// Register the palette data goes through
+    pub fn data_reg(&self) -> IoReg {
+        match self {
+            Self::Background => IoReg::Bcpd,
+            Self::Object => IoReg::Ocpd,
+        }
+    }
+}
+
+/// One of the four DMG grey levels
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Shade {
+    #[default]
+    White,
+    LightGrey,
+    DarkGrey,
+    Black,
+}
+
+/// Shade for each of the four color indices, as written to BGP, OBP0 or OBP1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DmgPalette(pub [Shade; 4]);
+
+impl DmgPalette {
+    /// White to black, what most DMG games use for the background
+    pub const DEFAULT: Self = Self([Shade::White, Shade::LightGrey, Shade::DarkGrey, Shade::Black]);
+}
+
+impl Default for DmgPalette {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl From<DmgPalette> for u8 {
+    fn from(value: DmgPalette) -> Self {
+        value.0.iter().enumerate().fold(0, |acc, (idx, &shade)| acc | (shade as u8) << (idx * 2))
+    }
+}
+
+/// DMG palette registers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DmgPaletteKind {
+    Background,
+    /// Used by objects without [ObjAttributeFlags::DMG_PALETTE1](super::objects::ObjAttributeFlags::DMG_PALETTE1)
+    Object0,
+    /// Used by objects with [ObjAttributeFlags::DMG_PALETTE1](super::objects::ObjAttributeFlags::DMG_PALETTE1)
+    Object1,
+}
+
+impl DmgPaletteKind {
+    pub fn reg(&self) -> IoReg {
+        match self {
+            Self::Background => IoReg::Bgp,
+            Self::Object0 => IoReg::Obp0,
+            Self::Object1 => IoReg::Obp1,
+        }
+    }
+}
+
+/// CGB color, a 15-bit BGR555 value: red in bits 0-4, green in bits 5-9 and blue in bits 10-14
+/// 
+/// Stored little endian in palette memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
 pub struct Color(pub u16);
 
 impl Color {
-    pub const BLACK: Self = Self(0);
-    pub const DARK_GREY: Self = Self(0b01010_01010_01010_0);
-    pub const LIGHT_GREY: Self = Self(0b10101_10101_10101_0);
-    pub const WHITE: Self = Self(u16::MAX);
-    pub const RED: Self = Self(u16::from_le_bytes([0b00000000, 0b00011111]));
-    pub const GREEN: Self = Self(u16::from_le_bytes([0b00000011, 0b11100000]));
-    pub const BLUE: Self = Self(u16::from_le_bytes([0b01111100, 0b00000000]));
+    pub const BLACK: Self = Self::new(0, 0, 0);
+    pub const DARK_GREY: Self = Self::new(10, 10, 10);
+    pub const LIGHT_GREY: Self = Self::new(21, 21, 21);
+    pub const WHITE: Self = Self::new(31, 31, 31);
+    pub const RED: Self = Self::new(31, 0, 0);
+    pub const GREEN: Self = Self::new(0, 31, 0);
+    pub const BLUE: Self = Self::new(0, 0, 31);
+
+    /// Color from 5-bit components, anything above 31 is masked off
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self((r as u16 & 0x1f) | (g as u16 & 0x1f) << 5 | (b as u16 & 0x1f) << 10)
+    }
+
+    /// Closest color to 24-bit `r`, `g`, `b`, scaled linearly
+    pub fn from_rgb888(r: u8, g: u8, b: u8) -> Self {
+        Self::from_rgb888_with(r, g, b, ColorCorrection::None)
+    }
+
+    /// Color that shows up closest to 24-bit `r`, `g`, `b` on a screen with `correction`
+    pub fn from_rgb888_with(r: u8, g: u8, b: u8, correction: ColorCorrection) -> Self {
+        let [r, g, b] = correction.to_components([r, g, b]);
+        Self::new(r, g, b)
+    }
+
+    /// Parses `rrggbb` or `#rrggbb`, see [Color::from_rgb888]
+    pub fn from_hex(hex: &str) -> Result<Self, ConversionError> {
+        Self::from_hex_with(hex, ColorCorrection::None)
+    }
+
+    pub fn from_hex_with(hex: &str, correction: ColorCorrection) -> Result<Self, ConversionError> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        if hex.len() != 6 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(ConversionError::InvalidHexColor);
+        }
+
+        let rgb = u32::from_str_radix(hex, 16).map_err(|_| ConversionError::InvalidHexColor)?;
+        let [_, r, g, b] = rgb.to_be_bytes();
+
+        Ok(Self::from_rgb888_with(r, g, b, correction))
+    }
+
+    /// 5-bit red component
+    pub const fn r(&self) -> u8 {
+        (self.0 & 0x1f) as u8
+    }
+
+    /// 5-bit green component
+    pub const fn g(&self) -> u8 {
+        (self.0 >> 5 & 0x1f) as u8
+    }
+
+    /// 5-bit blue component
+    pub const fn b(&self) -> u8 {
+        (self.0 >> 10 & 0x1f) as u8
+    }
+
+    /// 24-bit color, scaled linearly so black and white map to 0x000000 and 0xffffff
+    pub fn to_rgb888(&self) -> [u8; 3] {
+        self.to_rgb888_with(ColorCorrection::None)
+    }
+
+    /// 24-bit color as it shows up on a screen with `correction`, for previews
+    pub fn to_rgb888_with(&self, correction: ColorCorrection) -> [u8; 3] {
+        correction.to_rgb888([self.r(), self.g(), self.b()])
+    }
+
+    /// Little endian, the order palette memory takes it in
+    pub fn to_bytes(&self) -> [u8; 2] {
+        self.0.to_le_bytes()
+    }
+}
+
+/// How 5-bit components turn into what's seen on screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ColorCorrection {
+    /// Linear scaling, what most emulators show with color correction off
+    #[default]
+    None,
+    /// The washed out, channel bleeding colors of the CGB's LCD
+    Cgb,
+}
+
+impl ColorCorrection {
+    /// How much of each 5-bit component ends up in the red, green and blue of the CGB's LCD, in 32nds
+    const CGB_MIX: [[i32; 3]; 3] = [
+        [26, 4, 2],
+        [0, 24, 8],
+        [6, 4, 22],
+    ];
+
+    /// The CGB's LCD tops out at this, out of 4 * 255
+    const CGB_MAX: i32 = 960;
+
+    fn to_rgb888(self, components: [u8; 3]) -> [u8; 3] {
+        match self {
+            Self::None => components.map(|c| c << 3 | c >> 2),
+            Self::Cgb => Self::CGB_MIX.map(|row| {
+                let mixed: i32 = row.iter().zip(components).map(|(&weight, c)| weight * c as i32).sum();
+                (mixed.min(Self::CGB_MAX) / 4) as u8
+            }),
+        }
+    }
+
+    fn to_components(self, rgb: [u8; 3]) -> [u8; 3] {
+        match self {
+            Self::None => rgb.map(|c| ((c as u32 * 31 + 127) / 255) as u8),
+            Self::Cgb => {
+                // undo the mix, aiming for the middle of the range the LCD rounds down from
+                let target = rgb.map(|c| (c as f32 * 4.0 + 1.5).min(Self::CGB_MAX as f32));
+                let m = Self::CGB_MIX.map(|row| row.map(|weight| weight as f32));
+                let det = |m: [[f32; 3]; 3]| {
+                    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+                        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+                        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+                };
+
+                let whole = det(m);
+                std::array::from_fn(|column| {
+                    let mut replaced = m;
+                    for (row, &value) in replaced.iter_mut().zip(target.iter()) {
+                        row[column] = value;
+                    }
+
+                    (det(replaced) / whole).round().clamp(0.0, 31.0) as u8
+                })
+            }
+        }
+    }
 }
 
 impl BitOr<Color> for Color {
@@ -123,4 +330,40 @@ impl BitOr<Color> for Color {
     fn bitor(self, rhs: Color) -> Self::Output {
         Color(self.0 | rhs.0)
     }
-}
\ No newline at end of file
+}
+
+/// Colors last written to each CGB palette and DMG palette register, `None` until written
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Palettes {
+    pub background: [Option<[Color; 4]>; 8],
+    pub object: [Option<[Color; 4]>; 8],
+    /// BGP, OBP0 and OBP1
+    pub dmg: [Option<DmgPalette>; 3],
+}
+
+impl Palettes {
+    pub fn get(&self, kind: PaletteKind, palette: CgbPalette) -> Option<[Color; 4]> {
+        match kind {
+            PaletteKind::Background => self.background[usize::from(palette)],
+            PaletteKind::Object => self.object[usize::from(palette)],
+        }
+    }
+
+    pub fn set(&mut self, kind: PaletteKind, palette: CgbPalette, colors: [Color; 4]) -> &mut Self {
+        match kind {
+            PaletteKind::Background => self.background[usize::from(palette)] = Some(colors),
+            PaletteKind::Object => self.object[usize::from(palette)] = Some(colors),
+        }
+
+        self
+    }
+
+    pub fn get_dmg(&self, kind: DmgPaletteKind) -> Option<DmgPalette> {
+        self.dmg[kind as usize]
+    }
+
+    pub fn set_dmg(&mut self, kind: DmgPaletteKind, palette: DmgPalette) -> &mut Self {
+        self.dmg[kind as usize] = Some(palette);
+        self
+    }
+}
@@ -1,6 +1,8 @@
 use std::ops::Index;
 
-use super::{ConversionError, palettes::PaletteColor};
+use bitflags::bitflags;
+
+use super::{ConversionError, palettes::{CgbPalette, PaletteColor}};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct TileRow {
@@ -56,6 +58,19 @@ impl Tile {
         Self { pixel_data }
     }
 
+    /// Mirrored left to right, what [TileAttributeFlags::X_FLIP] shows
+    pub fn flipped_x(&self) -> Self {
+        Self { pixel_data: self.pixel_data.map(|row| TileRow { pixel_data: (row.pixel_data.0.reverse_bits(), row.pixel_data.1.reverse_bits()) }) }
+    }
+
+    /// Mirrored top to bottom, what [TileAttributeFlags::Y_FLIP] shows
+    pub fn flipped_y(&self) -> Self {
+        let mut pixel_data = self.pixel_data;
+        pixel_data.reverse();
+
+        Self { pixel_data }
+    }
+
     pub fn as_bytes(&self) -> [u8; 16] {
         self.pixel_data.into_iter().flat_map(|row| [row.pixel_data.0, row.pixel_data.1]).collect::<Vec<u8>>().try_into().unwrap()
     }
@@ -92,4 +107,71 @@ impl<'a> From<&'a Tilemap> for &'a [u8] {
     fn from(value: &'a Tilemap) -> Self {
         &value.map[..]
     }
-}
\ No newline at end of file
+}
+
+bitflags! {
+    /// Flag bits of a CGB BG map attribute, see [TileAttributes]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+    pub struct TileAttributeFlags: u8 {
+        /// Draws the tile over objects, whatever their own priority
+        const PRIORITY  = 0b10000000;
+        const Y_FLIP    = 0b01000000;
+        const X_FLIP    = 0b00100000;
+        /// Takes the tile data from VRAM bank 1
+        const BANK1     = 0b00001000;
+    }
+}
+
+/// Attribute byte of a single BG map entry, stored in VRAM bank 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct TileAttributes {
+    pub flags: TileAttributeFlags,
+    pub palette: CgbPalette,
+}
+
+impl From<TileAttributes> for u8 {
+    fn from(value: TileAttributes) -> Self {
+        let palette: u8 = value.palette.into();
+        value.flags.bits() | palette
+    }
+}
+
+/// Attributes for every entry of a [Tilemap], laid out the same way
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AttributeMap {
+    pub map: [TileAttributes; 32 * 32],
+}
+
+impl AttributeMap {
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.map.iter().map(|&attr| attr.into()).collect()
+    }
+}
+
+impl Default for AttributeMap {
+    fn default() -> Self {
+        Self { map: [TileAttributes::default(); 32 * 32] }
+    }
+}
+
+impl From<[TileAttributes; 32 * 32]> for AttributeMap {
+    fn from(value: [TileAttributes; 32 * 32]) -> Self {
+        Self { map: value }
+    }
+}
+
+impl Index<u8> for AttributeMap {
+    type Output = [TileAttributes];
+
+    fn index(&self, index: u8) -> &Self::Output {
+        &self.map[index as usize * 32..index as usize * 32 + 32]
+    }
+}
@@ -0,0 +1,103 @@
+use crate::cpu::CpuSpeed;
+
+/// Rate TIMA counts at, selected by the low bits of TAC
+/// 
+/// Named after their normal speed frequencies, they double in CGB double speed mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TimerClock {
+    /// CPU clock / 1024
+    #[default]
+    Hz4096 = 0b00,
+    /// CPU clock / 16
+    Hz262144 = 0b01,
+    /// CPU clock / 64
+    Hz65536 = 0b10,
+    /// CPU clock / 256
+    Hz16384 = 0b11,
+}
+
+impl TimerClock {
+    /// Fastest first
+    pub const ALL: [TimerClock; 4] = [Self::Hz262144, Self::Hz65536, Self::Hz16384, Self::Hz4096];
+
+    /// Frequency at normal speed
+    pub fn frequency(&self) -> u32 {
+        match self {
+            Self::Hz4096 => 4096,
+            Self::Hz262144 => 262144,
+            Self::Hz65536 => 65536,
+            Self::Hz16384 => 16384,
+        }
+    }
+
+    pub fn frequency_at(&self, speed: CpuSpeed) -> u32 {
+        self.frequency() * speed.multiplier()
+    }
+}
+
+/// Contents of TAC
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct TimerControl {
+    pub clock: TimerClock,
+    pub enable: bool,
+}
+
+impl From<TimerControl> for u8 {
+    fn from(value: TimerControl) -> Self {
+        (value.enable as u8) << 2 | value.clock as u8
+    }
+}
+
+/// A clock and a TMA reload value, making TIMA overflow (and request the Timer interrupt) at a fixed rate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimerConfig {
+    pub clock: TimerClock,
+    /// TIMA is reloaded with this on overflow, so it overflows every `256 - modulo` ticks of `clock`
+    pub modulo: u8,
+}
+
+impl TimerConfig {
+    /// Closest config to overflowing `hz` times a second at normal speed, preferring faster clocks on ties
+    /// 
+    /// `None` if `hz` is outside 16 Hz to 262144 Hz, the range the timer can hit at normal speed
+    pub fn for_frequency(hz: u32) -> Option<Self> {
+        Self::for_frequency_at(hz, CpuSpeed::Normal)
+    }
+
+    /// Like [TimerConfig::for_frequency], but with the CPU running at `speed`. Double speed doubles the range
+    pub fn for_frequency_at(hz: u32, speed: CpuSpeed) -> Option<Self> {
+        if hz == 0 {
+            return None;
+        }
+
+        let mut best: Option<(Self, u32)> = None;
+        for clock in TimerClock::ALL {
+            let ticks = (clock.frequency_at(speed) + hz / 2) / hz;
+            if !(1..=256).contains(&ticks) {
+                continue;
+            }
+
+            let config = Self { clock, modulo: (256 - ticks) as u8 };
+            let error = config.frequency_at(speed).abs_diff(hz);
+            if best.is_none_or(|(_, best_error)| error < best_error) {
+                best = Some((config, error));
+            }
+        }
+
+        best.map(|(config, _)| config)
+    }
+
+    /// Overflows per second at normal speed, rounded down
+    pub fn frequency(&self) -> u32 {
+        self.frequency_at(CpuSpeed::Normal)
+    }
+
+    pub fn frequency_at(&self, speed: CpuSpeed) -> u32 {
+        self.clock.frequency_at(speed) / self.ticks()
+    }
+
+    /// Ticks of the clock between overflows
+    pub fn ticks(&self) -> u32 {
+        256 - self.modulo as u32
+    }
+}